@@ -0,0 +1,81 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command as ProcessCommand, Stdio};
+
+use super::process::spawn_error;
+use crate::error::{Error, Result};
+
+/// A long-lived `git cat-file --batch` process that streams blob contents on request.
+///
+/// Spawning git once and feeding it `<commit>:<path>` lines avoids paying process
+/// start-up for every version of a file.
+#[derive(Debug)]
+pub(crate) struct CatFileBatch {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl CatFileBatch {
+    pub fn spawn() -> Result<Self> {
+        let mut child = ProcessCommand::new("git")
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(spawn_error)?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take()
+            .ok_or_else(|| Error::Git("Failed to read from git cat-file".to_string()))?;
+
+        Ok(CatFileBatch { child, stdin, stdout: BufReader::new(stdout) })
+    }
+
+    /// Type and contents of the object named by `spec` (anything `git rev-parse` accepts,
+    /// e.g. `HEAD:./src/main.rs`).
+    ///
+    /// `Ok(None)` means git answered that the object is missing; `Err` means the process
+    /// itself is no longer usable.
+    pub fn read(&mut self, spec: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let stdin = self.stdin.as_mut()
+            .ok_or_else(|| Error::Git("git cat-file is not running".to_string()))?;
+        writeln!(stdin, "{}", spec)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| Error::Git(format!("Failed to write to git cat-file: {}", e)))?;
+
+        // Header: "<oid> <type> <size>", or "<spec> missing" / "<spec> ambiguous"
+        let mut header = String::new();
+        let read = self.stdout.read_line(&mut header)
+            .map_err(|e| Error::Git(format!("Failed to read from git cat-file: {}", e)))?;
+        if read == 0 {
+            return Err(Error::Git("git cat-file exited unexpectedly".to_string()));
+        }
+
+        let header = header.trim_end();
+        let parts: Vec<&str> = header.rsplitn(3, ' ').collect();
+        if parts.len() < 3 {
+            return Ok(None);
+        }
+        let (size, object_type) = (parts[0], parts[1]);
+        let Ok(size) = size.parse::<usize>() else {
+            return Ok(None);
+        };
+
+        // Contents are followed by a single newline
+        let mut data = vec![0; size + 1];
+        self.stdout.read_exact(&mut data)
+            .map_err(|e| Error::Git(format!("Failed to read from git cat-file: {}", e)))?;
+        data.truncate(size);
+
+        Ok(Some((object_type.to_string(), data)))
+    }
+}
+
+impl Drop for CatFileBatch {
+    fn drop(&mut self) {
+        // Closing stdin tells git to exit once it has answered everything
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+use gix::bstr::{BStr, ByteSlice};
+use gix::ObjectId;
+
+use super::{count_matches, pickaxe_matcher, push_log_record, push_porcelain_line, GitBackend, LogQuery, LogRecord, PorcelainAuthor};
+use crate::diff::{trace_line_range, unified_diff, RangeHunk};
+use crate::error::{Error, Result};
+
+/// A commit that changed a file: its id, the file's blob in the first parent, its blob
+/// after, and the `--name-status` entry describing the change.
+type FileCommit = (ObjectId, Option<ObjectId>, Option<ObjectId>, Vec<String>);
+
+/// Reads history, blobs and blame in-process with gitoxide, without a `git` binary.
+///
+/// Paths are interpreted relative to the current directory, as they are for `git`.
+pub struct GixBackend {
+    repo: gix::ThreadSafeRepository,
+}
+
+impl GixBackend {
+    /// Open the repository containing the current directory.
+    pub fn discover() -> Result<Self> {
+        gix::ThreadSafeRepository::discover(".")
+            .map(|repo| GixBackend { repo })
+            .map_err(|_| Error::NotARepository)
+    }
+
+    /// `path` relative to the root of the repository rather than the current directory.
+    fn repo_path(&self, repo: &gix::Repository, path: &str) -> Result<String> {
+        let prefix = repo.prefix()
+            .map_err(|e| Error::Git(format!("Failed to locate working directory: {}", e)))?
+            .unwrap_or(Path::new(""));
+        let mut parts: Vec<String> = Vec::new();
+        for component in prefix.join(path).components() {
+            match component {
+                std::path::Component::ParentDir => {
+                    parts.pop();
+                }
+                std::path::Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+                _ => {}
+            }
+        }
+        Ok(parts.join("/"))
+    }
+
+    /// Commits reachable from HEAD that changed `path` (repository-relative), newest first.
+    /// Like `git log --follow`, merges are left out, side branches are not simplified away,
+    /// and history continues under the old name across renames, including renames with edits.
+    fn file_commits(&self, repo: &gix::Repository, path: &str) -> Result<Vec<FileCommit>> {
+        let mut path = path.to_string();
+        let head = repo.head_id().map_err(|e| Error::Git(format!("Failed to resolve HEAD: {}", e)))?;
+        let walk = head.ancestors()
+            .sorting(gix::revision::walk::Sorting::ByCommitTime(Default::default()))
+            .all()
+            .map_err(|e| Error::Git(format!("Failed to walk history: {}", e)))?;
+
+        let mut commits = Vec::new();
+        for info in walk {
+            let info = info.map_err(|e| Error::Git(format!("Failed to walk history: {}", e)))?;
+            // `git log --follow` shows no diff for merges, so it never lists them
+            if info.parent_ids.len() > 1 {
+                continue;
+            }
+            let blob = blob_id_at(repo, info.id, &path)?;
+            let mut parent = match info.parent_ids.first() {
+                Some(first_parent) => blob_id_at(repo, *first_parent, &path)?,
+                None => None,
+            };
+            if parent == blob {
+                continue;
+            }
+
+            let mut name_status = match (parent, blob) {
+                (None, _) => vec!["A".to_string(), path.clone()],
+                (Some(_), None) => vec!["D".to_string(), path.clone()],
+                (Some(_), Some(_)) => vec!["M".to_string(), path.clone()],
+            };
+            if let (None, Some(_), Some(first_parent)) = (parent, blob, info.parent_ids.first()) {
+                if let Some((from, source, similarity)) = renamed_from(repo, *first_parent, info.id, &path)? {
+                    name_status = vec![format!("R{:03}", similarity), from.clone(), path.clone()];
+                    parent = Some(source);
+                    path = from;
+                }
+            }
+            commits.push((info.id, parent, blob, name_status));
+        }
+        Ok(commits)
+    }
+
+    fn log_record(&self, repo: &gix::Repository, id: ObjectId) -> Result<LogRecord> {
+        let commit = repo.find_commit(id).map_err(|e| Error::Git(format!("Failed to read commit {}: {}", id, e)))?;
+        let author = commit.author().map_err(|e| Error::Git(format!("Failed to read author of {}: {}", id, e)))?;
+        let committer = commit.committer().map_err(|e| Error::Git(format!("Failed to read committer of {}: {}", id, e)))?;
+        let message = commit.message_raw().map_err(|e| Error::Git(format!("Failed to read message of {}: {}", id, e)))?;
+
+        Ok(LogRecord {
+            hash: id.to_string(),
+            parents: commit.parent_ids().map(|parent| parent.to_string()).collect(),
+            author_name: author.name.to_str_lossy().trim().to_string(),
+            author_email: author.email.to_str_lossy().trim().to_string(),
+            author_time: signature_time(&author).ok_or_else(|| Error::Git(format!("Failed to read author time of {}", id)))?,
+            committer_name: committer.name.to_str_lossy().trim().to_string(),
+            committer_email: committer.email.to_str_lossy().trim().to_string(),
+            committer_time: signature_time(&committer).ok_or_else(|| Error::Git(format!("Failed to read commit time of {}", id)))?,
+            message: message.to_str_lossy().into_owned(),
+            name_status: Vec::new(),
+            hunk: None,
+        })
+    }
+
+    fn author(&self, repo: &gix::Repository, id: ObjectId) -> Result<PorcelainAuthor> {
+        let commit = repo.find_commit(id).map_err(|e| Error::Git(format!("Failed to read commit {}: {}", id, e)))?;
+        let author = commit.author().map_err(|e| Error::Git(format!("Failed to read author of {}: {}", id, e)))?;
+        let time = author.time().map_err(|e| Error::Git(format!("Failed to read author time of {}: {}", id, e)))?;
+        let summary = commit.message()
+            .map(|message| message.summary().to_str_lossy().into_owned())
+            .unwrap_or_default();
+
+        let offset = time.offset.abs();
+        Ok(PorcelainAuthor {
+            name: author.name.to_str_lossy().trim().to_string(),
+            email: author.email.to_str_lossy().trim().to_string(),
+            time: time.seconds,
+            tz: format!("{}{:02}{:02}", if time.offset < 0 { '-' } else { '+' }, offset / 3600, offset % 3600 / 60),
+            summary,
+        })
+    }
+}
+
+fn signature_time(signature: &gix::actor::SignatureRef) -> Option<DateTime<FixedOffset>> {
+    let time = signature.time().ok()?;
+    let offset = FixedOffset::east_opt(time.offset)?;
+    DateTime::from_timestamp(time.seconds, 0).map(|utc| utc.with_timezone(&offset))
+}
+
+fn blob_id_at(repo: &gix::Repository, commit: ObjectId, path: &str) -> Result<Option<ObjectId>> {
+    let tree = repo.find_commit(commit)
+        .map_err(|e| Error::Git(format!("Failed to read commit {}: {}", commit, e)))?
+        .tree()
+        .map_err(|e| Error::Git(format!("Failed to read tree of {}: {}", commit, e)))?;
+    let entry = tree.lookup_entry_by_path(path)
+        .map_err(|e| Error::Git(format!("Failed to look up {} in {}: {}", path, commit, e)))?;
+    Ok(entry.filter(|entry| entry.mode().is_blob()).map(|entry| entry.object_id()))
+}
+
+/// Where `commit` renamed `path` from, relative to its first `parent`: the old path, the
+/// blob it had there and how similar the two are in percent, using git's default 50% threshold.
+fn renamed_from(repo: &gix::Repository, parent: ObjectId, commit: ObjectId, path: &str) -> Result<Option<(String, ObjectId, u32)>> {
+    let tree = |id: ObjectId| {
+        repo.find_commit(id)
+            .map_err(|e| Error::Git(format!("Failed to read commit {}: {}", id, e)))?
+            .tree()
+            .map_err(|e| Error::Git(format!("Failed to read tree of {}: {}", id, e)))
+    };
+    let options = gix::diff::Options::default().with_rewrites(Some(gix::diff::Rewrites::default()));
+    let changes = repo.diff_tree_to_tree(&tree(parent)?, &tree(commit)?, options)
+        .map_err(|e| Error::Git(format!("Failed to diff {} against {}: {}", commit, parent, e)))?;
+
+    Ok(changes.into_iter().find_map(|change| match change {
+        gix::object::tree::diff::ChangeDetached::Rewrite { source_location, source_id, diff, location, copy: false, .. }
+            if location == path => {
+            let similarity = diff.map_or(100, |stats| (stats.similarity * 100.0) as u32);
+            Some((source_location.to_str_lossy().into_owned(), source_id, similarity))
+        }
+        _ => None,
+    }))
+}
+
+fn read_blob(repo: &gix::Repository, id: ObjectId) -> Result<String> {
+    let blob = repo.find_blob(id).map_err(|e| Error::Git(format!("Failed to read blob {}: {}", id, e)))?;
+    String::from_utf8(blob.data.clone()).map_err(|e| Error::Parse(format!("Invalid UTF-8 in blob {}: {}", id, e)))
+}
+
+impl GitBackend for GixBackend {
+    fn log(&self, query: &LogQuery) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let entries: Vec<(ObjectId, Vec<String>, Option<RangeHunk>)> = match *query {
+            LogQuery::File { path } => {
+                let path = self.repo_path(&repo, path)?;
+                self.file_commits(&repo, &path)?
+                    .into_iter()
+                    .map(|(id, _, _, name_status)| (id, name_status, None))
+                    .collect()
+            }
+            LogQuery::Lines { path, start_line, end_line } => {
+                let path = self.repo_path(&repo, path)?;
+                let commits = self.file_commits(&repo, &path)?;
+                let head = commits.first()
+                    .and_then(|(_, _, blob, _)| *blob)
+                    .ok_or_else(|| Error::PathNotTracked { path: path.clone(), commit: None })?;
+                let line_count = read_blob(&repo, head)?.lines().count();
+                if start_line == 0 || start_line > line_count {
+                    return Err(Error::InvalidRange(format!("file {} has only {} lines", path, line_count)));
+                }
+
+                // Line history follows renames too, so each commit still says where the file was
+                let mut statuses: HashMap<ObjectId, Vec<String>> = commits.iter()
+                    .map(|(id, _, _, name_status)| (*id, name_status.clone()))
+                    .collect();
+                let revisions = commits.into_iter()
+                    .map_while(|(id, parent, blob, _)| blob.map(|blob| (id, parent, blob)))
+                    .map(|(id, parent, blob)| {
+                        let old = parent.map(|parent| read_blob(&repo, parent)).transpose()?;
+                        Ok((id, old, read_blob(&repo, blob)?))
+                    });
+                trace_line_range(revisions, start_line, end_line.min(line_count))?
+                    .into_iter()
+                    .map(|(id, hunk)| (id, statuses.remove(&id).unwrap_or_default(), Some(hunk)))
+                    .collect()
+            }
+            LogQuery::Pickaxe { path, pattern, regex } => {
+                let matcher = pickaxe_matcher(pattern, regex)?;
+                let path = self.repo_path(&repo, path)?;
+                let count = |blob: Option<ObjectId>| -> Result<usize> {
+                    let contents = blob.map(|blob| read_blob(&repo, blob)).transpose()?;
+                    Ok(count_matches(contents.as_deref(), &matcher))
+                };
+                let mut entries = Vec::new();
+                for (id, parent, blob, name_status) in self.file_commits(&repo, &path)? {
+                    if count(parent)? != count(blob)? {
+                        entries.push((id, name_status, None));
+                    }
+                }
+                entries
+            }
+            LogQuery::Commit { rev } => {
+                // Tags are peeled to the commit they point at, as git log does
+                let id = repo.rev_parse_single(rev)
+                    .ok()
+                    .and_then(|id| id.object().ok()?.peel_to_commit().ok())
+                    .ok_or_else(|| Error::UnknownRevision(rev.to_string()))?
+                    .id;
+                vec![(id, Vec::new(), None)]
+            }
+        };
+
+        let mut output = String::new();
+        for (id, name_status, hunk) in entries {
+            push_log_record(&mut output, &LogRecord { name_status, hunk, ..self.log_record(&repo, id)? });
+        }
+        Ok(output)
+    }
+
+    fn blame(&self, commit: &str, path: &str) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let repo_path = self.repo_path(&repo, path)?;
+        let suspect = repo.rev_parse_single(commit)
+            .map_err(|_| Error::UnknownRevision(commit.to_string()))?
+            .detach();
+        if blob_id_at(&repo, suspect, &repo_path)?.is_none() {
+            return Err(Error::missing_at(path, commit));
+        }
+
+        let outcome = repo.blame_file(BStr::new(&repo_path), suspect, gix::blame::Options::default())
+            .map_err(|e| Error::Git(format!("Git blame failed: {}", e)))?;
+        let contents = String::from_utf8(outcome.blob.clone())
+            .map_err(|e| Error::Parse(format!("Invalid UTF-8 in {}: {}", path, e)))?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        let mut authors: HashMap<ObjectId, PorcelainAuthor> = HashMap::new();
+        let mut output = String::new();
+        for entry in &outcome.entries {
+            let author = match authors.entry(entry.commit_id) {
+                Entry::Occupied(known) => known.into_mut(),
+                Entry::Vacant(slot) => slot.insert(self.author(&repo, entry.commit_id)?),
+            };
+            let hash = entry.commit_id.to_string();
+            for (final_index, source_index) in entry.range_in_blamed_file().zip(entry.range_in_source_file()) {
+                let content = lines.get(final_index).copied().unwrap_or("");
+                push_porcelain_line(&mut output, &hash, author, source_index + 1, final_index + 1, &repo_path, content);
+            }
+        }
+        Ok(output)
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let repo_path = self.repo_path(&repo, path)?;
+        let commit_id = repo.rev_parse_single(commit)
+            .map_err(|_| Error::UnknownRevision(commit.to_string()))?
+            .detach();
+        let parent = repo.find_commit(commit_id)
+            .map_err(|e| Error::Git(format!("Failed to read commit {}: {}", commit_id, e)))?
+            .parent_ids()
+            .next()
+            .map(|id| id.detach());
+
+        let old = match parent {
+            Some(parent) => blob_id_at(&repo, parent, &repo_path)?.map(|id| read_blob(&repo, id)).transpose()?,
+            None => None,
+        };
+        let new = blob_id_at(&repo, commit_id, &repo_path)?.map(|id| read_blob(&repo, id)).transpose()?;
+        Ok(unified_diff(&repo_path, old.as_deref().unwrap_or(""), new.as_deref().unwrap_or("")))
+    }
+
+    fn cat_file(&self, commit: &str, path: &str) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let repo_path = self.repo_path(&repo, path)?;
+        let commit_id = repo.rev_parse_single(commit)
+            .map_err(|_| Error::UnknownRevision(commit.to_string()))?
+            .detach();
+        let blob = blob_id_at(&repo, commit_id, &repo_path)?
+            .ok_or_else(|| Error::missing_at(path, commit))?;
+        read_blob(&repo, blob)
+    }
+
+    fn prefix(&self) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let prefix = self.repo_path(&repo, ".")?;
+        Ok(if prefix.is_empty() { prefix } else { format!("{}/", prefix) })
+    }
+
+    fn tags(&self) -> Result<String> {
+        let repo = self.repo.to_thread_local();
+        let references = repo.references().map_err(|e| Error::Git(format!("Failed to read references: {}", e)))?;
+        let tags = references.tags().map_err(|e| Error::Git(format!("Failed to read tags: {}", e)))?;
+        let mut output = String::new();
+        for tag in tags {
+            let mut tag = tag.map_err(|e| Error::Git(format!("Failed to read a tag: {}", e)))?;
+            let name = tag.name().shorten().to_string();
+            let line = match tag.peel_to_commit() {
+                Ok(commit) => {
+                    let time = commit.time().map(|time| time.seconds.to_string()).unwrap_or_default();
+                    format!("{} {} {}\n", commit.id, time, name)
+                }
+                // Trees and blobs can be tagged too, but have no time
+                Err(_) => match tag.peel_to_id() {
+                    Ok(id) => format!("{}  {}\n", id, name),
+                    Err(e) => return Err(Error::Git(format!("Failed to read tag {}: {}", name, e))),
+                },
+            };
+            output.push_str(&line);
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::ProcessBackend;
+    use crate::git::get_file_history;
+    use std::process::Command;
+
+    fn git(repo: &Path, time: i64, args: &[&str]) {
+        let date = format!("{} +0000", time);
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(repo)
+            .env("GIT_AUTHOR_NAME", "Alice Smith")
+            .env("GIT_AUTHOR_EMAIL", "alice@example.com")
+            .env("GIT_COMMITTER_NAME", "Alice Smith")
+            .env("GIT_COMMITTER_EMAIL", "alice@example.com")
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("HOME", repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+
+    fn commit(repo: &Path, time: i64, file: &str, contents: &str, message: &str) {
+        std::fs::write(repo.join(file), contents).unwrap();
+        git(repo, time, &["add", "-A"]);
+        git(repo, time, &["commit", "-qm", message]);
+    }
+
+    #[test]
+    fn follows_file_history_like_git_log() {
+        let repo = std::env::temp_dir().join(format!("garch-gix-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, 1_700_000_000, &["init", "-q", "-b", "main"]);
+
+        let lines: Vec<String> = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let text = |edits: &[(usize, &str)]| {
+            let mut lines = lines.clone();
+            for &(index, line) in edits {
+                lines[index] = format!("{}\n", line);
+            }
+            lines.concat()
+        };
+        commit(&repo, 1_700_000_000, "a.txt", &text(&[]), "add");
+
+        // A rename with an edit, which is only found by similarity
+        std::fs::remove_file(repo.join("a.txt")).unwrap();
+        commit(&repo, 1_700_000_100, "b.txt", &text(&[(0, "renamed")]), "rename with edit");
+
+        // A side branch merged back in, with a merge that differs from both sides
+        git(&repo, 1_700_000_200, &["checkout", "-qb", "side"]);
+        commit(&repo, 1_700_000_200, "b.txt", &text(&[(0, "renamed"), (9, "side")]), "side edit");
+        git(&repo, 1_700_000_300, &["checkout", "-q", "main"]);
+        commit(&repo, 1_700_000_300, "b.txt", &text(&[(0, "renamed"), (4, "main")]), "main edit");
+        git(&repo, 1_700_000_400, &["merge", "-q", "--no-edit", "side"]);
+
+        // A side branch whose change the merge throws away
+        git(&repo, 1_700_000_500, &["checkout", "-qb", "discarded"]);
+        commit(&repo, 1_700_000_500, "b.txt", &text(&[(2, "discarded")]), "discarded edit");
+        git(&repo, 1_700_000_600, &["checkout", "-q", "main"]);
+        commit(&repo, 1_700_000_600, "other.txt", "other\n", "elsewhere");
+        git(&repo, 1_700_000_700, &["merge", "-q", "--no-edit", "-s", "ours", "discarded"]);
+        commit(&repo, 1_700_000_800, "b.txt", &text(&[(0, "renamed"), (4, "main"), (9, "last")]), "last edit");
+
+        std::env::set_current_dir(&repo).unwrap();
+        let summary = |backend: &dyn GitBackend| -> Vec<(String, String, String)> {
+            get_file_history(backend, "b.txt")
+                .unwrap()
+                .into_iter()
+                .map(|commit| (commit.hash, commit.message, commit.path))
+                .collect()
+        };
+        let expected = summary(&ProcessBackend::new());
+        let actual = summary(&GixBackend::discover().unwrap());
+        let _ = std::fs::remove_dir_all(&repo);
+
+        let messages: Vec<&str> = expected.iter().map(|(_, message, _)| message.as_str()).collect();
+        assert_eq!(messages, ["last edit", "discarded edit", "main edit", "side edit", "rename with edit", "add"]);
+        assert_eq!(actual, expected);
+    }
+}
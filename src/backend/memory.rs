@@ -0,0 +1,352 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use similar::DiffTag;
+
+use super::{count_matches, pickaxe_matcher, push_log_record, push_porcelain_line, GitBackend, LogQuery, LogRecord, PorcelainAuthor};
+use crate::date::{from_git, unknown_time};
+use crate::diff::{diff_lines, trace_line_range, unified_diff, RangeHunk};
+use crate::error::{Error, Result};
+
+/// One commit for a [`MemoryBackend`]: the files it writes plus the metadata git would record.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryCommit {
+    pub author: String,
+    /// Author time in seconds since the Unix epoch, UTC.
+    pub time: i64,
+    pub message: String,
+    /// Files written by this commit, in order; `None` deletes the file.
+    pub files: Vec<(String, Option<String>)>,
+}
+
+impl MemoryCommit {
+    pub fn new(author: &str, time: i64, message: &str) -> Self {
+        MemoryCommit {
+            author: author.to_string(),
+            time,
+            message: message.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn write(mut self, path: &str, contents: &str) -> Self {
+        self.files.push((path.to_string(), Some(contents.to_string())));
+        self
+    }
+
+    pub fn delete(mut self, path: &str) -> Self {
+        self.files.push((path.to_string(), None));
+        self
+    }
+
+    fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
+    /// A made-up address for the author, since only the name is given.
+    fn email(&self) -> String {
+        format!("{}@example.com", self.author.to_lowercase().replace(' ', "."))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredCommit {
+    hash: String,
+    commit: MemoryCommit,
+    tree: BTreeMap<String, String>,
+}
+
+/// A fake repository with a single linear branch, held entirely in memory.
+///
+/// Answers every [`GitBackend`] query in git's own formats, so the history engine can be
+/// exercised without a repository on disk:
+///
+/// ```
+/// use garch::backend::{MemoryBackend, MemoryCommit};
+///
+/// let mut repo = MemoryBackend::new();
+/// repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add greeting").write("hello.txt", "hello\n"));
+/// repo.commit(MemoryCommit::new("Bob Jones", 1_700_086_400, "Shout").write("hello.txt", "hello\nHELLO\n"));
+///
+/// let history = garch::get_file_versions(&repo, "hello.txt").unwrap();
+/// assert_eq!(history.versions.len(), 2);
+/// assert!(history.skipped.is_empty());
+/// assert_eq!(history.versions[0].blame_lines[1].author, "Bob J.");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBackend {
+    commits: Vec<StoredCommit>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    /// Append `commit` on top of the current head and return its hash.
+    pub fn commit(&mut self, commit: MemoryCommit) -> String {
+        let mut tree = self.commits.last().map(|c| c.tree.clone()).unwrap_or_default();
+        for (path, contents) in &commit.files {
+            match contents {
+                Some(contents) => tree.insert(path.clone(), contents.clone()),
+                None => tree.remove(path),
+            };
+        }
+
+        let mut hasher = DefaultHasher::new();
+        (self.commits.len(), &commit.author, commit.time, &commit.message).hash(&mut hasher);
+        let hash = format!("{:016x}{:024x}", hasher.finish(), self.commits.len() + 1);
+
+        self.commits.push(StoredCommit { hash: hash.clone(), commit, tree });
+        hash
+    }
+
+    fn find(&self, rev: &str) -> Result<usize> {
+        if rev == "HEAD" && !self.commits.is_empty() {
+            return Ok(self.commits.len() - 1);
+        }
+        self.commits.iter()
+            .position(|c| !rev.is_empty() && c.hash.starts_with(rev))
+            .ok_or_else(|| Error::UnknownRevision(rev.to_string()))
+    }
+
+    fn file_at(&self, index: usize, path: &str) -> Option<&str> {
+        self.commits[index].tree.get(path).map(|s| s.as_str())
+    }
+
+    fn parent_file(&self, index: usize, path: &str) -> Option<&str> {
+        if index == 0 {
+            None
+        } else {
+            self.file_at(index - 1, path)
+        }
+    }
+
+    /// Commit `index` as `git log` would describe it; the author also commits.
+    fn log_record(&self, index: usize) -> LogRecord {
+        let stored = &self.commits[index];
+        let time = from_git(stored.commit.time, "+0000").unwrap_or_else(unknown_time);
+        LogRecord {
+            hash: stored.hash.clone(),
+            parents: index.checked_sub(1).map(|parent| self.commits[parent].hash.clone()).into_iter().collect(),
+            author_name: stored.commit.author.clone(),
+            author_email: stored.commit.email(),
+            author_time: time,
+            committer_name: stored.commit.author.clone(),
+            committer_email: stored.commit.email(),
+            committer_time: time,
+            message: stored.commit.message.clone(),
+            name_status: Vec::new(),
+            hunk: None,
+        }
+    }
+
+    /// Commits that changed `path`, newest first, each with its `--name-status` entry.
+    /// Like `git log --follow`, a file added with the contents of one the same commit
+    /// deleted continues that file's history under its old name.
+    fn file_log(&self, path: &str) -> Vec<(usize, Vec<String>)> {
+        let mut path = path.to_string();
+        let mut entries = Vec::new();
+        for index in (0..self.commits.len()).rev() {
+            let (new, old) = (self.file_at(index, &path), self.parent_file(index, &path));
+            if new == old {
+                continue;
+            }
+            let status = match (old, new) {
+                (None, Some(contents)) => match self.renamed_from(index, contents) {
+                    Some(from) => {
+                        let status = vec!["R100".to_string(), from.clone(), path.clone()];
+                        path = from;
+                        status
+                    }
+                    None => vec!["A".to_string(), path.clone()],
+                },
+                (Some(_), None) => vec!["D".to_string(), path.clone()],
+                _ => vec!["M".to_string(), path.clone()],
+            };
+            entries.push((index, status));
+        }
+        entries
+    }
+
+    /// The path commit `index` moved `contents` from, if it deleted a file holding exactly that.
+    fn renamed_from(&self, index: usize, contents: &str) -> Option<String> {
+        let parent = &self.commits[index.checked_sub(1)?].tree;
+        let tree = &self.commits[index].tree;
+        parent.iter()
+            .find(|(path, old)| old.as_str() == contents && !tree.contains_key(*path))
+            .map(|(path, _)| path.clone())
+    }
+
+    /// Commits touching `start_line..=end_line` of the head version, tracking the range backwards.
+    fn line_log(&self, path: &str, start_line: usize, end_line: usize) -> Result<Vec<(usize, RangeHunk)>> {
+        let head = self.find("HEAD")?;
+        let contents = self.file_at(head, path)
+            .ok_or_else(|| Error::PathNotTracked { path: path.to_string(), commit: None })?;
+        let line_count = contents.lines().count();
+        if start_line == 0 || start_line > line_count {
+            return Err(Error::InvalidRange(format!("file {} has only {} lines", path, line_count)));
+        }
+
+        // Only commits that changed the file, each paired with the version it replaced
+        let revisions = (0..=head)
+            .rev()
+            .map_while(|index| self.file_at(index, path).map(|new| (index, new)))
+            .filter(|&(index, new)| self.parent_file(index, path) != Some(new))
+            .map(|(index, new)| Ok((index, self.parent_file(index, path).map(|s| s.to_string()), new.to_string())));
+
+        trace_line_range(revisions, start_line, end_line.min(line_count))
+    }
+
+    /// For every line of `path` at `index`: the commit that introduced it and its line number there.
+    fn attribute(&self, index: usize, path: &str) -> Vec<(usize, usize)> {
+        let mut owners: Vec<(usize, usize)> = Vec::new();
+        for current in 0..=index {
+            let (Some(new), old) = (self.file_at(current, path), self.parent_file(current, path)) else {
+                owners.clear();
+                continue;
+            };
+            if old == Some(new) {
+                continue;
+            }
+
+            let mut next = Vec::new();
+            for op in diff_lines(old.unwrap_or(""), new) {
+                let (tag, old_range, new_range) = op.as_tag_tuple();
+                if tag == DiffTag::Equal {
+                    next.extend_from_slice(&owners[old_range]);
+                } else {
+                    next.extend(new_range.map(|line| (current, line + 1)));
+                }
+            }
+            owners = next;
+        }
+        owners
+    }
+}
+
+impl GitBackend for MemoryBackend {
+    fn log(&self, query: &LogQuery) -> Result<String> {
+        let records: Vec<LogRecord> = match *query {
+            LogQuery::File { path } => self.file_log(path)
+                .into_iter()
+                .map(|(index, name_status)| LogRecord { name_status, ..self.log_record(index) })
+                .collect(),
+            LogQuery::Lines { path, start_line, end_line } => self.line_log(path, start_line, end_line)?
+                .into_iter()
+                .map(|(index, hunk)| LogRecord { hunk: Some(hunk), ..self.log_record(index) })
+                .collect(),
+            LogQuery::Pickaxe { path, pattern, regex } => {
+                let matcher = pickaxe_matcher(pattern, regex)?;
+                self.file_log(path)
+                    .into_iter()
+                    // The entry names the file before the commit first and after it last
+                    .filter(|(index, name_status)| {
+                        let (old_path, new_path) = (&name_status[1], &name_status[name_status.len() - 1]);
+                        count_matches(self.parent_file(*index, old_path), &matcher)
+                            != count_matches(self.file_at(*index, new_path), &matcher)
+                    })
+                    .map(|(index, name_status)| LogRecord { name_status, ..self.log_record(index) })
+                    .collect()
+            }
+            LogQuery::Commit { rev } => vec![self.log_record(self.find(rev)?)],
+        };
+
+        let mut output = String::new();
+        for record in &records {
+            push_log_record(&mut output, record);
+        }
+        Ok(output)
+    }
+
+    fn blame(&self, commit: &str, path: &str) -> Result<String> {
+        let index = self.find(commit)?;
+        let contents = self.file_at(index, path)
+            .ok_or_else(|| Error::missing_at(path, commit))?;
+
+        let mut output = String::new();
+        for (final_line, ((owner, orig_line), content)) in self.attribute(index, path)
+            .into_iter()
+            .zip(contents.lines())
+            .enumerate()
+        {
+            let stored = &self.commits[owner];
+            let author = PorcelainAuthor {
+                name: stored.commit.author.clone(),
+                email: stored.commit.email(),
+                time: stored.commit.time,
+                tz: "+0000".to_string(),
+                summary: stored.commit.subject().to_string(),
+            };
+            push_porcelain_line(&mut output, &stored.hash, &author, orig_line, final_line + 1, path, content);
+        }
+        Ok(output)
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String> {
+        let index = self.find(commit)?;
+        let old = self.parent_file(index, path).unwrap_or("");
+        let new = self.file_at(index, path).unwrap_or("");
+        Ok(unified_diff(path, old, new))
+    }
+
+    fn cat_file(&self, commit: &str, path: &str) -> Result<String> {
+        let index = self.find(commit)?;
+        self.file_at(index, path)
+            .map(|s| s.to_string())
+            .ok_or_else(|| Error::missing_at(path, commit))
+    }
+
+    fn prefix(&self) -> Result<String> {
+        // Paths are always relative to the root of the in-memory tree
+        Ok(String::new())
+    }
+
+    fn tags(&self) -> Result<String> {
+        // Commits are only ever found by hash
+        Ok(String::new())
+    }
+}
+
+/// A [`MemoryBackend`] that counts the full blames asked of it.
+#[cfg(test)]
+pub(crate) struct CountingBackend {
+    pub repo: MemoryBackend,
+    pub blames: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(test)]
+impl CountingBackend {
+    pub fn new(repo: MemoryBackend) -> Self {
+        CountingBackend { repo, blames: Default::default() }
+    }
+}
+
+#[cfg(test)]
+impl GitBackend for CountingBackend {
+    fn log(&self, query: &LogQuery) -> Result<String> {
+        self.repo.log(query)
+    }
+
+    fn blame(&self, commit: &str, path: &str) -> Result<String> {
+        self.blames.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.repo.blame(commit, path)
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String> {
+        self.repo.show(commit, path)
+    }
+
+    fn cat_file(&self, commit: &str, path: &str) -> Result<String> {
+        self.repo.cat_file(commit, path)
+    }
+
+    fn prefix(&self) -> Result<String> {
+        self.repo.prefix()
+    }
+
+    fn tags(&self) -> Result<String> {
+        self.repo.tags()
+    }
+}
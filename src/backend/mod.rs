@@ -0,0 +1,169 @@
+//! Where git data comes from.
+//!
+//! Every backend speaks git's own output formats, so the parsing in
+//! [`crate::git`] and [`crate::blame`] is shared no matter which backend
+//! produced the text.
+
+mod batch;
+#[cfg(feature = "gix")]
+mod gix;
+mod memory;
+mod process;
+
+#[cfg(feature = "gix")]
+pub use self::gix::GixBackend;
+#[cfg(test)]
+pub(crate) use memory::CountingBackend;
+pub use memory::{MemoryBackend, MemoryCommit};
+pub use process::ProcessBackend;
+
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+
+use crate::diff::RangeHunk;
+use crate::error::{Error, Result};
+
+/// `--pretty` format used for every log query.
+///
+/// Each commit starts with an ASCII record separator and every field ends in a NUL,
+/// neither of which git allows in commit metadata, so messages come back intact
+/// whatever they contain. Anything after the last field, such as the patch that
+/// `git log -L` prints, is only used to find the file's path at that commit. Fields,
+/// in order: hash, space-separated parents, author name, email and strict ISO 8601
+/// date, committer name, email and date, and the raw message.
+pub const LOG_FORMAT: &str = "%x1e%H%x00%P%x00%an%x00%ae%x00%aI%x00%cn%x00%ce%x00%cI%x00%B%x00";
+
+/// Which commits a [`GitBackend::log`] call should list.
+#[derive(Debug, Clone, Copy)]
+pub enum LogQuery<'a> {
+    /// Every commit that touched the file, following renames (`git log --follow`).
+    /// Each record is followed by the file's `-z --name-status` entry, which names
+    /// the file as it was at that commit.
+    File { path: &'a str },
+    /// Commits that touched a line range (`git log -L start,end:path`).
+    Lines { path: &'a str, start_line: usize, end_line: usize },
+    /// Commits that changed how many times `pattern` occurs in the file, following renames
+    /// (`git log --follow -S pattern`, with `--pickaxe-regex` when `regex` is set). Records
+    /// carry the same `--name-status` entry as for [`LogQuery::File`].
+    Pickaxe { path: &'a str, pattern: &'a str, regex: bool },
+    /// The one commit `rev` names, such as a hash, tag or branch (`git log -1 rev`),
+    /// without a `--name-status` entry.
+    Commit { rev: &'a str },
+}
+
+/// What a [`LogQuery::Pickaxe`] counts, for backends that search history in-process.
+pub(crate) fn pickaxe_matcher(pattern: &str, regex: bool) -> Result<Regex> {
+    if pattern.is_empty() {
+        return Err(Error::InvalidArgument("The search pattern is empty".to_string()));
+    }
+    let pattern = if regex { pattern.to_string() } else { regex::escape(pattern) };
+    Regex::new(&pattern).map_err(|e| Error::InvalidArgument(format!("Invalid pattern '{}': {}", pattern, e)))
+}
+
+/// How many times `matcher` occurs in `contents` without overlapping, as `git log -S`
+/// counts; a file that doesn't exist has none.
+pub(crate) fn count_matches(contents: Option<&str>, matcher: &Regex) -> usize {
+    contents.map_or(0, |contents| matcher.find_iter(contents).count())
+}
+
+/// Source of raw git output for the history engine.
+pub trait GitBackend: Send + Sync {
+    /// Commits matching `query`, newest first, one per line in [`LOG_FORMAT`].
+    fn log(&self, query: &LogQuery) -> Result<String>;
+
+    /// `git blame --line-porcelain` output for `path` as of `commit`.
+    fn blame(&self, commit: &str, path: &str) -> Result<String>;
+
+    /// Unified diff of `path` introduced by `commit`, as printed by `git show`.
+    fn show(&self, commit: &str, path: &str) -> Result<String>;
+
+    /// Contents of `path` as of `commit` (`git cat-file blob commit:path`).
+    fn cat_file(&self, commit: &str, path: &str) -> Result<String>;
+
+    /// The current directory relative to the root of the working tree, ending in `/`
+    /// unless it is the root itself (`git rev-parse --show-prefix`). Paths in log
+    /// output are relative to the root, while every other method takes paths relative
+    /// to the current directory.
+    fn prefix(&self) -> Result<String>;
+
+    /// Every tag, one per line: the hash of the commit it points at (through any annotated
+    /// tag), that commit's time in seconds since the epoch, and the tag's name, separated by
+    /// spaces, as `git for-each-ref refs/tags` can be asked to print them. Tags of anything
+    /// other than a commit have no time.
+    fn tags(&self) -> Result<String>;
+}
+
+/// One commit in the fields of [`LOG_FORMAT`], for backends that read history in-process.
+pub(crate) struct LogRecord {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    pub author_time: DateTime<FixedOffset>,
+    pub committer_name: String,
+    pub committer_email: String,
+    pub committer_time: DateTime<FixedOffset>,
+    pub message: String,
+    /// The file's `-z --name-status` entry at this commit, such as `["M", path]` or
+    /// `["R100", old, new]`; empty when the path isn't known.
+    pub name_status: Vec<String>,
+    /// For [`LogQuery::Lines`], where the range sat around this commit's change, printed
+    /// as the hunk header of the patch `git log -L` shows.
+    pub hunk: Option<RangeHunk>,
+}
+
+/// Append `record` formatted as [`LOG_FORMAT`] would print it.
+pub(crate) fn push_log_record(output: &mut String, record: &LogRecord) {
+    let fields = [
+        record.hash.as_str(),
+        &record.parents.join(" "),
+        &record.author_name,
+        &record.author_email,
+        &record.author_time.to_rfc3339(),
+        &record.committer_name,
+        &record.committer_email,
+        &record.committer_time.to_rfc3339(),
+        &record.message,
+    ];
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output.push('\x1e');
+    for field in fields {
+        output.push_str(field);
+        output.push('\0');
+    }
+    if !record.name_status.is_empty() {
+        output.push('\n');
+        for field in &record.name_status {
+            output.push_str(field);
+            output.push('\0');
+        }
+    }
+    if let Some(hunk) = &record.hunk {
+        output.push('\n');
+        output.push_str(&hunk.header());
+        output.push('\n');
+    }
+}
+
+/// Commit metadata repeated in every `git blame --line-porcelain` entry.
+pub(crate) struct PorcelainAuthor {
+    pub name: String,
+    pub email: String,
+    pub time: i64,
+    pub tz: String,
+    pub summary: String,
+}
+
+/// Append one `--line-porcelain` entry, for backends that compute blame in-process.
+pub(crate) fn push_porcelain_line(output: &mut String, hash: &str, author: &PorcelainAuthor, orig_line: usize, final_line: usize, path: &str, content: &str) {
+    output.push_str(&format!("{} {} {} 1\n", hash, orig_line, final_line));
+    output.push_str(&format!("author {}\n", author.name));
+    output.push_str(&format!("author-mail <{}>\n", author.email));
+    output.push_str(&format!("author-time {}\n", author.time));
+    output.push_str(&format!("author-tz {}\n", author.tz));
+    output.push_str(&format!("summary {}\n", author.summary));
+    output.push_str(&format!("filename {}\n", path));
+    output.push_str(&format!("\t{}\n", content));
+}
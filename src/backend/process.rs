@@ -0,0 +1,158 @@
+use std::process::Command as ProcessCommand;
+use std::sync::Mutex;
+
+use super::batch::CatFileBatch;
+use super::{GitBackend, LogQuery, LOG_FORMAT};
+use crate::error::{Error, Result};
+
+/// Shells out to the `git` binary on `PATH`, run from the current directory.
+///
+/// File contents come from a single `git cat-file --batch` process that is started on
+/// first use and kept alive for the lifetime of the backend.
+#[derive(Debug, Default)]
+pub struct ProcessBackend {
+    batch: Mutex<Option<CatFileBatch>>,
+}
+
+impl ProcessBackend {
+    pub fn new() -> Self {
+        ProcessBackend::default()
+    }
+
+    fn read_object(&self, spec: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let mut batch = self.batch.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // A process that died since the last request gets one fresh replacement
+        for attempt in 0..2 {
+            if batch.is_none() {
+                *batch = Some(CatFileBatch::spawn()?);
+            }
+            match batch.as_mut().map(|reader| reader.read(spec)) {
+                Some(Ok(object)) => return Ok(object),
+                Some(Err(e)) if attempt > 0 => return Err(e),
+                _ => *batch = None,
+            }
+        }
+        unreachable!("the second attempt always returns")
+    }
+}
+
+/// The error for a `git` process that couldn't be started.
+pub(crate) fn spawn_error(e: std::io::Error) -> Error {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        Error::GitNotFound(e.to_string())
+    } else {
+        Error::Git(format!("Failed to run git command: {}", e))
+    }
+}
+
+/// The error git's complaint on stderr describes, for a command about `path` at `commit`.
+fn command_error(stderr: &str, path: &str, commit: Option<&str>) -> Error {
+    let message = stderr.trim().trim_start_matches("fatal: ").to_string();
+    if stderr.contains("not a git repository") {
+        Error::NotARepository
+    } else if stderr.contains("no such path")
+        || stderr.contains("There is no path")
+        || stderr.contains("does not exist in")
+        || stderr.contains("exists on disk, but not in")
+    {
+        Error::PathNotTracked { path: path.to_string(), commit: commit.map(|commit| commit.to_string()) }
+    } else if stderr.contains("bad revision") || stderr.contains("unknown revision") || stderr.contains("Not a valid object name") {
+        Error::UnknownRevision(commit.unwrap_or("HEAD").to_string())
+    } else if stderr.contains("has only") && stderr.contains("lines") {
+        Error::InvalidRange(message)
+    } else {
+        Error::Git(message)
+    }
+}
+
+fn run_git(args: &[&str], path: &str, commit: Option<&str>) -> Result<String> {
+    let output = ProcessCommand::new("git")
+        .args(args)
+        .output()
+        .map_err(spawn_error)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(command_error(&stderr, path, commit));
+    }
+
+    String::from_utf8(output.stdout)
+        .map_err(|e| Error::Parse(format!("Invalid UTF-8 in git output: {}", e)))
+}
+
+/// `--format` for [`GitBackend::tags`]: annotated tags are peeled to their commit, whose
+/// committer date is then the only one of the two dates that is set.
+const TAG_FORMAT: &str =
+    "--format=%(if)%(*objectname)%(then)%(*objectname)%(else)%(objectname)%(end) %(*committerdate:unix)%(committerdate:unix) %(refname:strip=2)";
+
+impl GitBackend for ProcessBackend {
+    fn log(&self, query: &LogQuery) -> Result<String> {
+        let pretty = format!("--pretty=format:{}", LOG_FORMAT);
+        match *query {
+            LogQuery::File { path } => run_git(&[
+                "log",
+                "--follow",
+                "-z",
+                "--name-status",
+                &pretty,
+                "--",
+                path,
+            ], path, None),
+            LogQuery::Lines { path, start_line, end_line } => run_git(&[
+                "log",
+                // Note: --follow is not compatible with -L, git will track renames automatically for -L
+                "-L", &format!("{},{}:{}", start_line, end_line, path),
+                &pretty,
+            ], path, None),
+            LogQuery::Pickaxe { path, pattern, regex } => {
+                let pickaxe = format!("-S{}", pattern);
+                let mut args = vec!["log", "--follow", "-z", "--name-status", pickaxe.as_str()];
+                if regex {
+                    args.push("--pickaxe-regex");
+                }
+                args.extend([pretty.as_str(), "--", path]);
+                run_git(&args, path, None)
+            }
+            LogQuery::Commit { rev } => run_git(&["log", "-1", &pretty, "--end-of-options", rev, "--"], "", Some(rev)),
+        }
+    }
+
+    fn blame(&self, commit: &str, path: &str) -> Result<String> {
+        run_git(&[
+            "blame",
+            "--line-porcelain",
+            commit,
+            "--",
+            path,
+        ], path, Some(commit))
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String> {
+        run_git(&["show", "--format=", commit, "--", path], path, Some(commit))
+    }
+
+    fn cat_file(&self, commit: &str, path: &str) -> Result<String> {
+        // "./" makes git resolve the path against the current directory, like blame and log do
+        let spec = if path.starts_with('/') {
+            format!("{}:{}", commit, path)
+        } else {
+            format!("{}:./{}", commit, path)
+        };
+
+        match self.read_object(&spec)? {
+            Some((object_type, data)) if object_type == "blob" => String::from_utf8(data)
+                .map_err(|e| Error::Parse(format!("Invalid UTF-8 in {}: {}", path, e))),
+            Some((object_type, _)) => Err(Error::InvalidArgument(format!("{} is a {}, not a file", path, object_type))),
+            None => Err(Error::missing_at(path, commit)),
+        }
+    }
+
+    fn prefix(&self) -> Result<String> {
+        let output = run_git(&["rev-parse", "--show-prefix"], ".", None)?;
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+
+    fn tags(&self) -> Result<String> {
+        run_git(&["for-each-ref", TAG_FORMAT, "refs/tags"], ".", None)
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::{DateTime, FixedOffset};
+use similar::DiffTag;
+
+use crate::backend::GitBackend;
+use crate::date::{from_git, short_date, unknown_time};
+use crate::diff::diff_line_slices;
+use crate::git::CommitInfo;
+use crate::highlight::highlight_lines;
+
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub line_number: usize,
+    pub author: String,
+    /// Day the line was authored, in the author's timezone (`YYYY-MM-DD`).
+    pub date: String,
+    /// When the line was authored, in the author's timezone.
+    pub author_time: DateTime<FixedOffset>,
+    pub commit_hash: String,
+    pub commit_message: String,
+    pub content: String,
+    pub highlighted_content: String,
+}
+
+/// An author's name as blamed lines show it: the first name and the initial of the second.
+pub fn abbreviate_author(author: &str) -> String {
+    let parts: Vec<&str> = author.split_whitespace().collect();
+    if parts.len() >= 2 {
+        format!("{} {}.", parts[0], parts[1].chars().next().unwrap_or('?'))
+    } else {
+        author.to_string()
+    }
+}
+
+/// Parse `git blame --line-porcelain` output, pre-rendering syntax highlighting for `file_path`.
+pub fn parse_blame_output_with_highlighting(blame_text: &str, file_path: &str) -> Vec<BlameLine> {
+    let mut blame_lines = Vec::new();
+    let lines: Vec<&str> = blame_text.lines().collect();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if let Some(line) = lines.get(i) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() >= 3 && parts[0].len() >= 7 {
+                let commit_hash = parts[0].to_string();
+                let line_number: usize = parts[2].parse().unwrap_or(0);
+
+                // Look for author, date, and summary in the following lines
+                let mut author = String::new();
+                let mut author_seconds = None;
+                let mut author_tz = "+0000";
+                let mut commit_message = String::new();
+                let mut content = String::new();
+
+                i += 1;
+                while i < lines.len() {
+                    if let Some(info_line) = lines.get(i) {
+                        if let Some(value) = info_line.strip_prefix("author ") {
+                            author = value.to_string();
+                        } else if let Some(value) = info_line.strip_prefix("author-time ") {
+                            author_seconds = value.parse::<i64>().ok();
+                        } else if let Some(value) = info_line.strip_prefix("author-tz ") {
+                            author_tz = value;
+                        } else if let Some(value) = info_line.strip_prefix("summary ") {
+                            commit_message = value.to_string();
+                        } else if let Some(value) = info_line.strip_prefix('\t') {
+                            content = value.to_string(); // Remove leading tab
+                            i += 1;
+                            break;
+                        }
+                    }
+                    i += 1;
+                }
+
+                // author-tz comes after author-time, so the two are combined once both are read
+                let author_time = author_seconds
+                    .and_then(|seconds| from_git(seconds, author_tz))
+                    .unwrap_or_else(unknown_time);
+                blame_lines.push(BlameLine {
+                    line_number,
+                    author: abbreviate_author(&author),
+                    date: short_date(&author_time),
+                    author_time,
+                    commit_hash: commit_hash[..7].to_string(),
+                    commit_message,
+                    content,
+                    highlighted_content: String::new(),
+                });
+            } else {
+                i += 1;
+            }
+        } else {
+            break;
+        }
+    }
+
+    let contents: Vec<&str> = blame_lines.iter().map(|line| line.content.as_str()).collect();
+    let highlighted = highlight_lines(&contents, file_path);
+    for (line, highlighted_content) in blame_lines.iter_mut().zip(highlighted) {
+        line.highlighted_content = highlighted_content;
+    }
+
+    blame_lines
+}
+
+/// Whether [`derive_blame`] can build `commit`'s blame from `previous`, the blame of an
+/// older version of the file at `previous_path`: only when that is exactly the file the
+/// commit's parent had, as read through `backend`.
+///
+/// Commits that touched other files usually sit between two versions, so the older one
+/// needn't be the commit itself; but once branches have been merged it can be from
+/// another branch altogether. A merge's diff against its first parent holds lines that
+/// other commits wrote, so merges are never derived.
+pub fn can_derive_blame(backend: &dyn GitBackend, commit: &CommitInfo, previous: &[BlameLine], previous_path: &str) -> bool {
+    let [parent] = commit.parents.as_slice() else {
+        return false;
+    };
+    backend.cat_file(parent, previous_path)
+        .is_ok_and(|contents| contents.lines().eq(previous.iter().map(|line| line.content.as_str())))
+}
+
+/// Blame for the version of `file_path` that `commit` produced, derived from the blame of
+/// its parent's version (see [`can_derive_blame`]).
+///
+/// Lines the commit left alone keep their previous attribution; everything its diff added
+/// or rewrote is attributed to `commit`. Unlike a full `git blame`, lines that were moved
+/// from elsewhere are attributed to `commit` as well.
+pub fn derive_blame(previous: &[BlameLine], contents: &str, commit: &CommitInfo, file_path: &str) -> Vec<BlameLine> {
+    let old_lines: Vec<&str> = previous.iter().map(|line| line.content.as_str()).collect();
+    let new_lines: Vec<&str> = contents.lines().collect();
+    let author = abbreviate_author(&commit.author);
+    let short_hash: String = commit.hash.chars().take(7).collect();
+
+    let mut blame_lines = Vec::with_capacity(new_lines.len());
+    for op in diff_line_slices(&old_lines, &new_lines) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            for (old_index, new_index) in old_range.zip(new_range) {
+                blame_lines.push(BlameLine {
+                    line_number: new_index + 1,
+                    ..previous[old_index].clone()
+                });
+            }
+        } else {
+            for new_index in new_range {
+                blame_lines.push(BlameLine {
+                    line_number: new_index + 1,
+                    author: author.clone(),
+                    date: commit.date.clone(),
+                    author_time: commit.time,
+                    commit_hash: short_hash.clone(),
+                    commit_message: commit.message.clone(),
+                    content: new_lines[new_index].to_string(),
+                    highlighted_content: String::new(),
+                });
+            }
+        }
+    }
+
+    // Highlighting depends on the surrounding lines, so redo it for the whole version
+    let highlighted = highlight_lines(&new_lines, file_path);
+    for (line, highlighted_content) in blame_lines.iter_mut().zip(highlighted) {
+        line.highlighted_content = highlighted_content;
+    }
+
+    blame_lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MemoryBackend, MemoryCommit};
+    use crate::git::{get_blame_for_commit, get_commit, get_file_content};
+
+    fn attribution(blame_lines: &[BlameLine]) -> Vec<(usize, &str, &str, &str)> {
+        blame_lines.iter()
+            .map(|line| (line.line_number, line.author.as_str(), line.commit_hash.as_str(), line.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn derived_blame_matches_a_full_blame() {
+        let mut repo = MemoryBackend::new();
+        let first = repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.rs", "fn a() {}\nfn b() {}\nfn c() {}\n"));
+        let second = repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_100, "Edit").write("f.rs", "fn a() {}\nfn b2() {}\nfn c() {}\nfn d() {}\n"));
+
+        let previous = get_blame_for_commit(&repo, &first, "f.rs").unwrap();
+        let commit = get_commit(&repo, &second).unwrap();
+        let contents = get_file_content(&repo, &second, "f.rs").unwrap();
+        assert!(can_derive_blame(&repo, &commit, &previous, "f.rs"));
+
+        let derived = derive_blame(&previous, &contents, &commit, "f.rs");
+        let full = get_blame_for_commit(&repo, &second, "f.rs").unwrap();
+        assert_eq!(attribution(&derived), attribution(&full));
+        assert_eq!(derived[1].author, "Bob J.");
+        assert_eq!(derived[2].author, "Alice S.");
+    }
+
+    #[test]
+    fn derives_blame_across_commits_to_other_files() {
+        let mut repo = MemoryBackend::new();
+        let first = repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.rs", "one\ntwo\n"));
+        repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_100, "Unrelated").write("g.rs", "other\n"));
+        let third = repo.commit(MemoryCommit::new("Carol White", 1_700_000_200, "Edit").write("f.rs", "one\nTWO\n"));
+
+        // The version before isn't the parent, but the parent still has that version's file
+        let previous = get_blame_for_commit(&repo, &first, "f.rs").unwrap();
+        let commit = get_commit(&repo, &third).unwrap();
+        assert_ne!(commit.parents, [first]);
+        assert!(can_derive_blame(&repo, &commit, &previous, "f.rs"));
+
+        let contents = get_file_content(&repo, &third, "f.rs").unwrap();
+        let derived = derive_blame(&previous, &contents, &commit, "f.rs");
+        let full = get_blame_for_commit(&repo, &third, "f.rs").unwrap();
+        assert_eq!(attribution(&derived), attribution(&full));
+    }
+
+    #[test]
+    fn only_derives_blame_from_the_file_the_parent_had() {
+        let mut repo = MemoryBackend::new();
+        let first = repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.rs", "one\n"));
+        let second = repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_100, "Edit").write("f.rs", "two\n"));
+        let third = repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_200, "Edit again").write("f.rs", "three\n"));
+
+        // The version before in log order can be from another branch after a merge
+        let commit = get_commit(&repo, &third).unwrap();
+        let first_blame = get_blame_for_commit(&repo, &first, "f.rs").unwrap();
+        let second_blame = get_blame_for_commit(&repo, &second, "f.rs").unwrap();
+        assert!(can_derive_blame(&repo, &commit, &second_blame, "f.rs"));
+        assert!(!can_derive_blame(&repo, &commit, &first_blame, "f.rs"));
+        assert!(!can_derive_blame(&repo, &commit, &second_blame, "g.rs"));
+
+        // A merge's diff against either parent holds lines the other branch wrote
+        let mut merge = commit.clone();
+        merge.parents.push(first.clone());
+        assert!(!can_derive_blame(&repo, &merge, &second_blame, "f.rs"));
+
+        let mut root = commit;
+        root.parents.clear();
+        assert!(!can_derive_blame(&repo, &root, &second_blame, "f.rs"));
+    }
+}
@@ -0,0 +1,266 @@
+//! Persistent storage for blame results, so reopening a file doesn't repeat `git blame`.
+//!
+//! A commit hash pins down a file's entire history, so the blame of a path at a
+//! commit never changes and entries never need invalidating. Each entry is a small
+//! text file under `blame/<commit>/`, written atomically so concurrent loaders and
+//! interrupted runs can't leave a partial entry behind.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+use chrono::DateTime;
+
+use crate::blame::BlameLine;
+use crate::date::short_date;
+use crate::error::{Error, Result};
+use crate::highlight::highlight_lines;
+
+/// First line of every entry. Bump the version whenever the stored fields change,
+/// so entries written by older releases are ignored instead of misread.
+const ENTRY_HEADER: &str = "garch-blame 2";
+
+/// Blame results stored on disk, keyed by commit hash and repository-relative path.
+#[derive(Debug, Clone)]
+pub struct BlameCache {
+    dir: PathBuf,
+    /// Working tree root, used to turn paths relative to the current directory into
+    /// keys that don't depend on where garch was run from
+    root: PathBuf,
+}
+
+/// Size of the cache on disk.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub commits: usize,
+    pub bytes: u64,
+}
+
+impl BlameCache {
+    /// The cache for the repository containing the current directory: in
+    /// `$XDG_CACHE_HOME/garch` when that is set, otherwise in `.git/garch`.
+    pub fn open() -> Result<Self> {
+        let cwd = std::env::current_dir().map_err(|e| Error::Io(format!("Failed to read current directory: {}", e)))?;
+        let (root, git_dir) = find_repository(&cwd).ok_or(Error::NotARepository)?;
+
+        let dir = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+            // Commit hashes are global, so one cache can serve every repository
+            Some(cache_home) => PathBuf::from(cache_home).join("garch"),
+            None => git_dir.join("garch"),
+        };
+        Ok(BlameCache { dir, root })
+    }
+
+    /// A cache stored in `dir` for the working tree at `root`.
+    pub fn at(dir: impl Into<PathBuf>, root: impl Into<PathBuf>) -> Self {
+        BlameCache { dir: dir.into(), root: root.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The stored blame of `file_path` at `commit`, highlighted afresh.
+    pub fn get(&self, commit: &str, file_path: &str) -> Option<Vec<BlameLine>> {
+        let key = self.key(file_path)?;
+        let text = fs::read_to_string(self.entry_path(commit, &key)?).ok()?;
+        let mut lines = text.lines();
+
+        // The key's path is stored too, in case two paths hash to the same file name
+        if lines.next()? != ENTRY_HEADER || unescape(lines.next()?) != key {
+            return None;
+        }
+
+        let mut blame_lines = Vec::new();
+        for line in lines {
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            let [line_number, author, author_time, commit_hash, commit_message, content] = fields.try_into().ok()?;
+            let author_time = DateTime::parse_from_rfc3339(&author_time).ok()?;
+            blame_lines.push(BlameLine {
+                line_number: line_number.parse().ok()?,
+                author,
+                date: short_date(&author_time),
+                author_time,
+                commit_hash,
+                commit_message,
+                content,
+                highlighted_content: String::new(),
+            });
+        }
+
+        let contents: Vec<&str> = blame_lines.iter().map(|line| line.content.as_str()).collect();
+        let highlighted = highlight_lines(&contents, file_path);
+        for (line, highlighted_content) in blame_lines.iter_mut().zip(highlighted) {
+            line.highlighted_content = highlighted_content;
+        }
+        Some(blame_lines)
+    }
+
+    /// Store the blame of `file_path` at `commit`. Highlighting isn't stored; it is
+    /// recomputed on the way out.
+    pub fn put(&self, commit: &str, file_path: &str, blame_lines: &[BlameLine]) -> Result<()> {
+        let key = self.key(file_path)
+            .ok_or_else(|| Error::InvalidArgument(format!("{} is outside the repository", file_path)))?;
+        let path = self.entry_path(commit, &key)
+            .ok_or_else(|| Error::UnknownRevision(commit.to_string()))?;
+
+        let mut text = format!("{}\n{}\n", ENTRY_HEADER, escape(&key));
+        for line in blame_lines {
+            let fields = [
+                line.line_number.to_string(),
+                escape(&line.author),
+                line.author_time.to_rfc3339(),
+                escape(&line.commit_hash),
+                escape(&line.commit_message),
+                escape(&line.content),
+            ];
+            text.push_str(&fields.join("\t"));
+            text.push('\n');
+        }
+
+        let parent = path.parent().expect("entries live in a commit directory");
+        fs::create_dir_all(parent).map_err(|e| Error::Io(format!("Failed to create {}: {}", parent.display(), e)))?;
+
+        // Write under a name no other process uses, then move it into place in one step
+        let temporary = parent.join(format!(".{}.{}", key_hash(&key), process::id()));
+        fs::File::create(&temporary)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .and_then(|_| fs::rename(&temporary, &path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temporary);
+                Error::Io(format!("Failed to write {}: {}", path.display(), e))
+            })
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        let commits = match fs::read_dir(self.dir.join("blame")) {
+            Ok(commits) => commits,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => return Err(Error::Io(format!("Failed to read {}: {}", self.dir.display(), e))),
+        };
+
+        for commit in commits.flatten() {
+            let Ok(entries) = fs::read_dir(commit.path()) else {
+                continue;
+            };
+            stats.commits += 1;
+            for entry in entries.flatten() {
+                if let Ok(metadata) = entry.metadata() {
+                    stats.entries += 1;
+                    stats.bytes += metadata.len();
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Delete every stored entry.
+    pub fn clear(&self) -> Result<()> {
+        let blame_dir = self.dir.join("blame");
+        match fs::remove_dir_all(&blame_dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::Io(format!("Failed to remove {}: {}", blame_dir.display(), e))),
+            _ => Ok(()),
+        }
+    }
+
+    /// `file_path` relative to the working tree root, with `/` separators.
+    fn key(&self, file_path: &str) -> Option<String> {
+        let cwd = std::env::current_dir().ok()?;
+        let mut parts: Vec<String> = Vec::new();
+        for component in cwd.join(file_path).components() {
+            match component {
+                Component::ParentDir => {
+                    parts.pop();
+                }
+                Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+                _ => {}
+            }
+        }
+
+        let root: Vec<String> = self.root.components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        parts.starts_with(&root).then(|| parts[root.len()..].join("/"))
+    }
+
+    fn entry_path(&self, commit: &str, key: &str) -> Option<PathBuf> {
+        // Only full hashes are stable keys; names like HEAD move
+        let is_hash = commit.len() >= 40 && commit.chars().all(|c| c.is_ascii_hexdigit());
+        is_hash.then(|| self.dir.join("blame").join(commit).join(format!("{:016x}", key_hash(key))))
+    }
+}
+
+/// The working tree root and git directory of the repository containing `start`.
+fn find_repository(start: &Path) -> Option<(PathBuf, PathBuf)> {
+    for dir in start.ancestors() {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some((dir.to_path_buf(), dot_git));
+        }
+        // Linked worktrees and submodules have a `.git` file pointing at the real directory
+        if let Ok(text) = fs::read_to_string(&dot_git) {
+            if let Some(git_dir) = text.trim().strip_prefix("gitdir: ") {
+                return Some((dir.to_path_buf(), dir.join(git_dir)));
+            }
+        }
+    }
+    None
+}
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same file names across Rust releases.
+fn key_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_fields_round_trip() {
+        for field in ["plain", "", "tab\there", "line\nbreak\r\n", "back\\slash", "literal \\n and \\t", "trailing\\", "\\\\\t"] {
+            let escaped = escape(field);
+            assert!(!escaped.contains(['\t', '\n', '\r']), "{:?} escaped to {:?}", field, escaped);
+            assert_eq!(unescape(&escaped), field);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Commit and blame timestamps: which timezone they are shown in, and how they are written.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Utc};
+
+use crate::error::{Error, Result};
+
+/// Which clock times are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayZone {
+    /// The timezone the author was in, as `git log` and `git blame` show by default.
+    #[default]
+    Author,
+    /// This machine's timezone (honours `TZ`).
+    Local,
+    Utc,
+}
+
+impl DisplayZone {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "author" => Some(DisplayZone::Author),
+            "local" => Some(DisplayZone::Local),
+            "utc" => Some(DisplayZone::Utc),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayZone::Author => "author",
+            DisplayZone::Local => "local",
+            DisplayZone::Utc => "utc",
+        }
+    }
+
+    /// The same instant as `time`, on this zone's clock.
+    pub fn convert(self, time: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            DisplayZone::Author => *time,
+            DisplayZone::Local => time.with_timezone(&Local).fixed_offset(),
+            DisplayZone::Utc => time.with_timezone(&Utc).fixed_offset(),
+        }
+    }
+}
+
+/// `seconds` since the Unix epoch, in the offset git writes as `author-tz` (e.g. `-0430`).
+pub fn from_git(seconds: i64, tz: &str) -> Option<DateTime<FixedOffset>> {
+    let sign = match tz.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = tz.get(1..5)?;
+    let hours: i32 = digits.get(..2)?.parse().ok()?;
+    let minutes: i32 = digits.get(2..)?.parse().ok()?;
+    let offset = FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))?;
+    DateTime::from_timestamp(seconds, 0).map(|time| time.with_timezone(&offset))
+}
+
+/// A date as `git log --date=iso-strict` prints it. Plain `YYYY-MM-DD` dates, as
+/// `--date=short` prints them, are read as midnight UTC.
+pub fn parse_git_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date.trim()).ok().or_else(|| {
+        let day = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()?;
+        Some(day.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset())
+    })
+}
+
+/// A moment typed as `YYYY-MM-DD HH:MM[:SS]` (or with a `T` for the space), on no clock in
+/// particular. A plain `YYYY-MM-DD` is the last second of that day, so that it takes in
+/// everything that happened on it.
+///
+/// ```
+/// use garch::date::parse_moment;
+///
+/// assert_eq!(parse_moment("2024-01-15").unwrap().to_string(), "2024-01-15 23:59:59");
+/// assert_eq!(parse_moment("2024-01-15T09:30").unwrap().to_string(), "2024-01-15 09:30:00");
+/// assert!(parse_moment("yesterday").is_none());
+/// ```
+pub fn parse_moment(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim().replacen('T', " ", 1);
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()?.and_hms_opt(23, 59, 59))
+}
+
+/// The Unix epoch, for times git didn't report.
+pub fn unknown_time() -> DateTime<FixedOffset> {
+    DateTime::UNIX_EPOCH.fixed_offset()
+}
+
+/// The calendar day of `time` on its own clock, as `YYYY-MM-DD`.
+pub fn short_date(time: &DateTime<FixedOffset>) -> String {
+    time.format("%Y-%m-%d").to_string()
+}
+
+/// How a date is written out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    /// `2024-01-15`, like `git log --date=short`.
+    #[default]
+    Short,
+    /// `3 months ago`, like `git log --date=relative`.
+    Relative,
+    /// `2024-01-15 14:30:22 +0100`, like `git log --date=iso`.
+    Iso,
+    /// `Mon Jan 15 14:30:22 2024 +0100`, like `git log`'s default.
+    Full,
+    /// A strftime-style pattern such as `%d %b %Y`.
+    Custom(String),
+}
+
+impl DateFormat {
+    /// `short`, `relative`, `iso`, `full`, or a custom pattern written either as
+    /// `format:<pattern>` (as git spells it) or as any string containing `%`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec {
+            "short" => Ok(DateFormat::Short),
+            "relative" => Ok(DateFormat::Relative),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "full" | "default" => Ok(DateFormat::Full),
+            _ => {
+                let pattern = spec.strip_prefix("format:").unwrap_or(spec);
+                if !spec.starts_with("format:") && !pattern.contains('%') {
+                    return Err(Error::InvalidArgument(format!("Unknown date format '{}': expected short, relative, iso, full or a %-pattern", spec)));
+                }
+                // chrono panics when displaying an invalid pattern, so reject it up front
+                if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+                    return Err(Error::InvalidArgument(format!("Invalid date pattern '{}'", pattern)));
+                }
+                Ok(DateFormat::Custom(pattern.to_string()))
+            }
+        }
+    }
+
+    /// `time` on `zone`'s clock in this format, with relative dates counted back from now.
+    pub fn format(&self, time: &DateTime<FixedOffset>, zone: DisplayZone) -> String {
+        self.format_at(time, zone, Utc::now())
+    }
+
+    /// Like [`format`](DateFormat::format), with relative dates counted back from `now`.
+    pub fn format_at(&self, time: &DateTime<FixedOffset>, zone: DisplayZone, now: DateTime<Utc>) -> String {
+        let time = zone.convert(time);
+        match self {
+            DateFormat::Short => short_date(&time),
+            DateFormat::Relative => relative_date(&time, now),
+            DateFormat::Iso => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            DateFormat::Full => time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+            DateFormat::Custom(pattern) => time.format(pattern).to_string(),
+        }
+    }
+}
+
+/// How long before `now` `time` was, rounded the way `git log --date=relative` does.
+pub fn relative_date(time: &DateTime<FixedOffset>, now: DateTime<Utc>) -> String {
+    let seconds = now.signed_duration_since(time).num_seconds();
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+
+    let plural = |count: i64, unit: &str| format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" });
+    if seconds < 90 {
+        return plural(seconds, "second");
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return plural(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return plural(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return plural(days, "day");
+    }
+    if days < 70 {
+        return plural((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return plural((days + 15) / 30, "month");
+    }
+
+    // Past five years only whole years are worth mentioning
+    let total_months = (days * 12 * 2 + 365) / (365 * 2);
+    let (years, months) = (total_months / 12, total_months % 12);
+    if years < 5 && months > 0 {
+        let years = format!("{} year{}", years, if years == 1 { "" } else { "s" });
+        return format!("{}, {}", years, plural(months, "month"));
+    }
+    plural((days + 183) / 365, "year")
+}
@@ -0,0 +1,467 @@
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm, DiffOp, DiffTag, TextDiff};
+
+use crate::error::Result;
+
+#[derive(Debug, Clone)]
+pub struct LineChange {
+    pub line_number: usize,
+    pub change_type: ChangeType,
+    pub content: String,
+    /// For `Modified`, the line `content` replaced.
+    pub old_content: Option<String>,
+    /// For `Modified`, which words differ between `old_content` and `content`.
+    pub word_diff: Option<WordDiff>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Added,
+    Removed,
+    /// A removed line paired with the added line that took its place.
+    Modified,
+}
+
+/// The parts of two versions of a line that differ, as byte ranges into each.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordDiff {
+    pub old: Vec<Range<usize>>,
+    pub new: Vec<Range<usize>>,
+}
+
+/// One line of a diff hunk: unchanged context, or a line added or removed.
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    /// `None` for context lines.
+    pub change_type: Option<ChangeType>,
+    /// 1-based line number in the old version. An added line has the number of the old
+    /// line it was inserted before.
+    pub old_line: usize,
+    /// 1-based line number in the new version. A removed line has the number of the new
+    /// line that took its place.
+    pub new_line: usize,
+    pub content: String,
+    /// Pre-rendered syntax highlighting of `content`, the same as `content` until highlighted.
+    pub highlighted_content: String,
+    /// For an added or removed line paired with one on the other side of the change, the
+    /// byte ranges of `content` that differ from it.
+    pub changed_ranges: Option<Vec<Range<usize>>>,
+}
+
+/// One `@@` section of a unified diff.
+#[derive(Debug, Clone)]
+pub struct DiffHunk {
+    /// The `@@ -a,b +c,d @@` line, with any function context git prints after it.
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Parse the first file section of a unified diff (as printed by `git show`) into hunks,
+/// context lines included.
+///
+/// ```
+/// use garch::diff::parse_diff_hunks;
+///
+/// let diff = "--- a/f.txt\n+++ b/f.txt\n@@ -1,2 +1,2 @@\n one\n-two\n+2\n";
+/// let hunks = parse_diff_hunks(diff);
+/// assert_eq!(hunks[0].header, "@@ -1,2 +1,2 @@");
+/// assert_eq!(hunks[0].lines.len(), 3);
+/// assert_eq!((hunks[0].lines[2].old_line, hunks[0].lines[2].new_line), (3, 2));
+/// ```
+pub fn parse_diff_hunks(diff_text: &str) -> Vec<DiffHunk> {
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    let (mut old_line, mut new_line) = (0, 0);
+
+    for line in diff_text.lines() {
+        // Each @@ hunk header starts a new hunk
+        if line.starts_with("@@") {
+            // Parse the line numbers from @@ -old_start,old_count +new_start,new_count @@
+            let start_of = |marker: char| {
+                let side = line.split(' ').find_map(|field| field.strip_prefix(marker)).unwrap_or("1");
+                let (start, count) = side.split_once(',').unwrap_or((side, "1"));
+                let start: usize = start.parse().unwrap_or(1);
+                // An empty side gives the line before the hunk, so it starts one further on
+                if count == "0" { start + 1 } else { start }
+            };
+            old_line = start_of('-');
+            new_line = start_of('+');
+            hunks.push(DiffHunk { header: line.to_string(), lines: Vec::new() });
+            continue;
+        }
+
+        let Some(hunk) = hunks.last_mut() else {
+            continue;
+        };
+
+        // Stop at the next commit or end of diff
+        if line.starts_with("commit ") || line.starts_with("diff --git") {
+            break;
+        }
+
+        let (change_type, content) = if let Some(content) = line.strip_prefix('+') {
+            (Some(ChangeType::Added), content)
+        } else if let Some(content) = line.strip_prefix('-') {
+            (Some(ChangeType::Removed), content)
+        } else if let Some(content) = line.strip_prefix(' ') {
+            (None, content)
+        } else {
+            // "\ No newline at end of file" and anything else that isn't a line
+            continue;
+        };
+
+        hunk.lines.push(DiffLine {
+            change_type,
+            old_line,
+            new_line,
+            content: content.to_string(),
+            highlighted_content: content.to_string(),
+            changed_ranges: None,
+        });
+        if change_type != Some(ChangeType::Added) {
+            old_line += 1;
+        }
+        if change_type != Some(ChangeType::Removed) {
+            new_line += 1;
+        }
+    }
+
+    for hunk in &mut hunks {
+        pair_changes(&mut hunk.lines);
+    }
+    hunks
+}
+
+/// Pair each run of removed lines with the run of added lines after it, first with first,
+/// and record which words of each pair changed.
+fn pair_changes(lines: &mut [DiffLine]) {
+    let mut start = 0;
+    while start < lines.len() {
+        let removed = lines[start..].iter().take_while(|line| line.change_type == Some(ChangeType::Removed)).count();
+        let added = lines[start + removed..].iter().take_while(|line| line.change_type == Some(ChangeType::Added)).count();
+        for offset in 0..removed.min(added) {
+            let words = word_diff(&lines[start + offset].content, &lines[start + removed + offset].content);
+            lines[start + offset].changed_ranges = Some(words.old);
+            lines[start + removed + offset].changed_ranges = Some(words.new);
+        }
+        start += (removed + added).max(1);
+    }
+}
+
+/// Parse the first file section of a unified diff (as printed by `git show`) into line changes.
+///
+/// A removed line directly paired with an added one comes back as a single `Modified`
+/// change, numbered and worded as the new line.
+///
+/// ```
+/// use garch::diff::parse_diff_output;
+/// use garch::ChangeType;
+///
+/// let changes = parse_diff_output("@@ -1,2 +1,2 @@\n-let x = 1;\n+let x = 2;\n keep\n");
+/// assert_eq!(changes.len(), 1);
+/// assert_eq!(changes[0].change_type, ChangeType::Modified);
+/// assert_eq!(changes[0].old_content.as_deref(), Some("let x = 1;"));
+/// assert_eq!(changes[0].word_diff.as_ref().unwrap().new, [8..9]);
+/// ```
+pub fn parse_diff_output(diff_text: &str) -> Vec<LineChange> {
+    let mut changes = Vec::new();
+    for hunk in parse_diff_hunks(diff_text) {
+        // Removed lines waiting for the added lines they pair with
+        let mut replaced: Vec<DiffLine> = Vec::new();
+        for line in hunk.lines {
+            let Some(change_type) = line.change_type else {
+                continue;
+            };
+            if change_type == ChangeType::Removed && line.changed_ranges.is_some() {
+                replaced.push(line);
+                continue;
+            }
+
+            let old = match line.changed_ranges {
+                Some(new_ranges) if change_type == ChangeType::Added && !replaced.is_empty() => {
+                    let old = replaced.remove(0);
+                    Some((old.content, WordDiff { old: old.changed_ranges.unwrap_or_default(), new: new_ranges }))
+                }
+                _ => None,
+            };
+            let (old_content, word_diff) = old.unzip();
+            changes.push(LineChange {
+                // Removed lines are numbered by where they would have been in the new version
+                line_number: line.new_line,
+                change_type: if old_content.is_some() { ChangeType::Modified } else { change_type },
+                content: line.content,
+                old_content,
+                word_diff,
+            });
+        }
+    }
+    changes
+}
+
+/// Which words of `old` and `new` differ, comparing them a word, a run of whitespace or
+/// a single other character at a time.
+///
+/// When the two have nothing but whitespace in common, nothing is singled out, since
+/// marking every word of both would say no more than that the whole line changed.
+///
+/// ```
+/// use garch::diff::word_diff;
+///
+/// let words = word_diff("return f.read()", "return f.read().strip()");
+/// assert!(words.old.is_empty());
+/// assert_eq!(words.new, [15..23]);
+/// ```
+pub fn word_diff(old: &str, new: &str) -> WordDiff {
+    let (old_tokens, new_tokens) = (tokenize(old), tokenize(new));
+    let old_words: Vec<&str> = old_tokens.iter().map(|range| &old[range.clone()]).collect();
+    let new_words: Vec<&str> = new_tokens.iter().map(|range| &new[range.clone()]).collect();
+
+    let mut words = WordDiff::default();
+    let mut shared = false;
+    for op in capture_diff_slices(Algorithm::Myers, &old_words, &new_words) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            shared |= old_words[old_range].iter().any(|word| !word.trim().is_empty());
+            continue;
+        }
+        // Ranges of consecutive changed tokens merge into one
+        let span = |tokens: &[Range<usize>], range: Range<usize>| {
+            (!range.is_empty()).then(|| tokens[range.start].start..tokens[range.end - 1].end)
+        };
+        words.old.extend(span(&old_tokens, old_range));
+        words.new.extend(span(&new_tokens, new_range));
+    }
+
+    if shared { words } else { WordDiff::default() }
+}
+
+/// Byte ranges of the words (runs of letters, digits and `_`), runs of whitespace and
+/// remaining single characters that make up `line`.
+fn tokenize(line: &str) -> Vec<Range<usize>> {
+    let class = |c: char| if c.is_alphanumeric() || c == '_' { 0 } else if c.is_whitespace() { 1 } else { 2 };
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut last_class = None;
+    for (index, c) in line.char_indices() {
+        let current = class(c);
+        match tokens.last_mut() {
+            Some(token) if current != 2 && last_class == Some(current) => token.end = index + c.len_utf8(),
+            _ => tokens.push(index..index + c.len_utf8()),
+        }
+        last_class = Some(current);
+    }
+    tokens
+}
+
+/// One row of two versions shown side by side: a line from either of them or from both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlignedRow {
+    /// 0-based index into the old lines, when the row has one.
+    pub old: Option<usize>,
+    /// 0-based index into the new lines, when the row has one.
+    pub new: Option<usize>,
+    /// `None` when the line is the same on both sides. `Modified` pairs a line with the
+    /// one that replaced it.
+    pub change_type: Option<ChangeType>,
+}
+
+/// Line up `old` and `new` for showing them next to each other: unchanged lines share a
+/// row, each changed block is paired off line by line, and whichever side of a block is
+/// shorter is padded with rows that only have a line on the other side.
+///
+/// ```
+/// use garch::diff::align_lines;
+/// use garch::ChangeType;
+///
+/// let rows = align_lines(&["a", "b", "c"], &["a", "B", "B2", "c"]);
+/// let pairs: Vec<_> = rows.iter().map(|row| (row.old, row.new, row.change_type)).collect();
+/// assert_eq!(pairs, [
+///     (Some(0), Some(0), None),
+///     (Some(1), Some(1), Some(ChangeType::Modified)),
+///     (None, Some(2), Some(ChangeType::Added)),
+///     (Some(2), Some(3), None),
+/// ]);
+/// ```
+pub fn align_lines(old: &[&str], new: &[&str]) -> Vec<AlignedRow> {
+    let mut rows = Vec::new();
+    for op in diff_line_slices(old, new) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        for offset in 0..old_range.len().max(new_range.len()) {
+            let old = (offset < old_range.len()).then(|| old_range.start + offset);
+            let new = (offset < new_range.len()).then(|| new_range.start + offset);
+            let change_type = match (old, new) {
+                _ if tag == DiffTag::Equal => None,
+                (Some(_), Some(_)) => Some(ChangeType::Modified),
+                (Some(_), None) => Some(ChangeType::Removed),
+                _ => Some(ChangeType::Added),
+            };
+            rows.push(AlignedRow { old, new, change_type });
+        }
+    }
+    rows
+}
+
+/// Line-level edit script turning `old` into `new`.
+pub(crate) fn diff_lines(old: &str, new: &str) -> Vec<DiffOp> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    diff_line_slices(&old_lines, &new_lines)
+}
+
+/// Edit script between two files that are already split into lines.
+pub(crate) fn diff_line_slices(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    capture_diff_slices(Algorithm::Myers, old, new)
+}
+
+/// Follow the 1-based range `start_line..=end_line` on the new side of `ops` back to the old side.
+///
+/// Returns whether any change touched the range, along with the range it occupied before
+/// the change (`None` when every line in it was newly added).
+pub(crate) fn map_range_to_old(ops: &[DiffOp], start_line: usize, end_line: usize) -> (bool, Option<(usize, usize)>) {
+    let start = start_line.saturating_sub(1);
+    let end = end_line;
+    let mut touched = false;
+    let mut old_start = usize::MAX;
+    let mut old_end = 0;
+
+    for op in ops {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            let overlap_start = new_range.start.max(start);
+            let overlap_end = new_range.end.min(end);
+            if overlap_start < overlap_end {
+                old_start = old_start.min(old_range.start + (overlap_start - new_range.start));
+                old_end = old_end.max(old_range.start + (overlap_end - new_range.start));
+            }
+            continue;
+        }
+
+        let intersects = if new_range.is_empty() {
+            // Pure deletion: only counts when it sits strictly inside the range
+            start < new_range.start && new_range.start < end
+        } else {
+            new_range.start < end && new_range.end > start
+        };
+        if intersects {
+            touched = true;
+            if !old_range.is_empty() {
+                old_start = old_start.min(old_range.start);
+                old_end = old_end.max(old_range.end);
+            }
+        }
+    }
+
+    if old_start < old_end {
+        (touched, Some((old_start + 1, old_end)))
+    } else {
+        (touched, None)
+    }
+}
+
+/// Where a traced line range sat before and after one commit, both 1-based and inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RangeHunk {
+    /// `None` when the commit added every line in the range.
+    pub old: Option<(usize, usize)>,
+    pub new: (usize, usize),
+}
+
+impl RangeHunk {
+    /// The hunk header `git log -L` prints for this change, such as `@@ -10,3 +12,4 @@`.
+    pub fn header(&self) -> String {
+        let side = |range: Option<(usize, usize)>| match range {
+            Some((start, end)) => format!("{},{}", start, end + 1 - start),
+            None => "0,0".to_string(),
+        };
+        format!("@@ -{} +{} @@", side(self.old), side(Some(self.new)))
+    }
+}
+
+/// Follow `start_line..=end_line` backwards through a file's history, the way `git log -L` does.
+///
+/// `revisions` yields `(id, parent_contents, contents)` newest first, one item per commit that
+/// changed the file. Returns the ids of the revisions whose change touched the range, each
+/// with where the range sat on either side of that change.
+pub(crate) fn trace_line_range<T, I>(revisions: I, start_line: usize, end_line: usize) -> Result<Vec<(T, RangeHunk)>>
+where
+    I: IntoIterator<Item = Result<(T, Option<String>, String)>>,
+{
+    let mut range = (start_line, end_line);
+    let mut matches = Vec::new();
+    for revision in revisions {
+        let (id, old, new) = revision?;
+        let ops = diff_lines(old.as_deref().unwrap_or(""), &new);
+        let (touched, old_range) = map_range_to_old(&ops, range.0, range.1);
+        if touched {
+            matches.push((id, RangeHunk { old: old_range.filter(|_| old.is_some()), new: range }));
+        }
+        match old_range {
+            Some(old_range) if old.is_some() => range = old_range,
+            _ => break,
+        }
+    }
+    Ok(matches)
+}
+
+/// Render the change from `old` to `new` as `git show` would print it for `path`.
+pub(crate) fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+    format!("diff --git a/{0} b/{0}\n{1}",
+        path,
+        diff.unified_diff().header(&format!("a/{}", path), &format!("b/{}", path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_a_range_back_across_an_insertion() {
+        let ops = diff_lines("a\nb\nc\n", "a\nx\nb\nc\n");
+
+        assert_eq!(map_range_to_old(&ops, 3, 4), (false, Some((2, 3))));
+        assert_eq!(map_range_to_old(&ops, 2, 2), (true, None));
+        assert_eq!(map_range_to_old(&ops, 1, 3), (true, Some((1, 2))));
+    }
+
+    #[test]
+    fn counts_a_deletion_only_inside_the_range() {
+        let ops = diff_lines("a\nb\nc\nd\n", "a\nb\nd\n");
+
+        assert_eq!(map_range_to_old(&ops, 2, 3), (true, Some((2, 4))));
+        assert_eq!(map_range_to_old(&ops, 3, 3), (false, Some((4, 4))));
+    }
+
+    #[test]
+    fn traces_a_range_through_the_commits_that_touched_it() {
+        let v1 = "a\nb\nc\nd\ne\nf\n";
+        let v2 = "a\nB\nc\nd\ne\nf\n";
+        let v3 = "z\na\nB\nc\nd\ne\nF\n";
+        let revisions = || [(3, Some(v2), v3), (2, Some(v1), v2), (1, None, v1)]
+            .map(|(id, old, new)| Ok((id, old.map(str::to_string), new.to_string())));
+
+        let traced = trace_line_range(revisions(), 3, 3).unwrap();
+        assert_eq!(traced, [
+            (2, RangeHunk { old: Some((2, 2)), new: (2, 2) }),
+            (1, RangeHunk { old: None, new: (2, 2) }),
+        ]);
+        assert_eq!(traced[0].1.header(), "@@ -2,1 +2,1 @@");
+
+        let traced = trace_line_range(revisions(), 6, 7).unwrap();
+        let ids: Vec<i32> = traced.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [3, 1]);
+        assert_eq!(traced[0].1, RangeHunk { old: Some((5, 6)), new: (6, 7) });
+    }
+
+    #[test]
+    fn stops_tracing_once_every_line_was_added() {
+        let revisions = [(2, Some("a\n"), "a\nb\n"), (1, None, "a\n")]
+            .map(|(id, old, new)| Ok((id, old.map(str::to_string), new.to_string())));
+
+        let traced = trace_line_range(revisions, 2, 2).unwrap();
+        assert_eq!(traced, [(2, RangeHunk { old: None, new: (2, 2) })]);
+    }
+}
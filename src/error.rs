@@ -0,0 +1,56 @@
+//! Everything that can go wrong while reading history.
+
+use std::fmt;
+
+/// Why a git query or a version of a file couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The `git` binary couldn't be run; the reason the OS gave.
+    GitNotFound(String),
+    /// The current directory isn't inside a git repository.
+    NotARepository,
+    /// `path` doesn't exist at `commit`, or anywhere in history when there is no commit.
+    PathNotTracked { path: String, commit: Option<String> },
+    /// A revision that doesn't name a commit.
+    UnknownRevision(String),
+    /// A line range that falls outside the file.
+    InvalidRange(String),
+    /// Git failed for some other reason; the message it printed.
+    Git(String),
+    /// Output that couldn't be understood, such as a file that isn't UTF-8.
+    Parse(String),
+    /// Reading or writing the blame cache failed.
+    Io(String),
+    /// An option value that doesn't make sense, such as an unknown date format.
+    InvalidArgument(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// `path` isn't part of `commit`.
+    pub fn missing_at(path: &str, commit: &str) -> Self {
+        Error::PathNotTracked { path: path.to_string(), commit: Some(commit.to_string()) }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::GitNotFound(reason) => write!(f, "Failed to run git (is it installed and on PATH?): {}", reason),
+            Error::NotARepository => write!(f, "Not inside a git repository"),
+            Error::PathNotTracked { path, commit: Some(commit) } => {
+                write!(f, "{} does not exist in commit {}", path, commit.chars().take(8).collect::<String>())
+            }
+            Error::PathNotTracked { path, commit: None } => write!(f, "{} is not tracked by git", path),
+            Error::UnknownRevision(revision) => write!(f, "Unknown revision '{}'", revision),
+            Error::InvalidRange(message) => write!(f, "Invalid line range: {}", message),
+            Error::Git(message) => write!(f, "Git command failed: {}", message),
+            Error::Parse(message) => write!(f, "Failed to parse git output: {}", message),
+            Error::Io(message) => write!(f, "{}", message),
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::backend::{GitBackend, LogQuery};
+use crate::blame::{parse_blame_output_with_highlighting, BlameLine};
+use crate::date::{from_git, parse_git_date, short_date};
+use crate::diff::{diff_lines, map_range_to_old, parse_diff_hunks, parse_diff_output, ChangeType, DiffHunk, LineChange};
+use crate::error::{Error, Result};
+use crate::highlight::highlight_lines;
+
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub hash: String,
+    /// Author date in the author's timezone (`YYYY-MM-DD`).
+    pub date: String,
+    /// Author time in the author's timezone.
+    pub time: DateTime<FixedOffset>,
+    pub author: String,
+    pub author_email: String,
+    pub committer: String,
+    pub committer_email: String,
+    /// Commit time in the committer's timezone.
+    pub committer_time: DateTime<FixedOffset>,
+    /// Full hashes of the parent commits; more than one for a merge.
+    pub parents: Vec<String>,
+    /// The subject: the message's first paragraph, on one line.
+    pub message: String,
+    /// Everything in the message after the subject, trailers included.
+    pub body: String,
+    /// `Key: value` lines from the final paragraph of the message, such as `Signed-off-by`.
+    pub trailers: Vec<(String, String)>,
+    /// The file's path at this commit, relative to the current directory like the path
+    /// its history was asked for. Differs from that path before a rename; empty when
+    /// git didn't say.
+    pub path: String,
+    /// For line history, where each traced range was in this commit's version of the
+    /// file (1-based, inclusive), following them as code around them moved. One entry
+    /// per range, in the order they were asked for; `None` for a range that didn't exist
+    /// yet, and empty when git didn't say.
+    pub line_ranges: Vec<Option<(usize, usize)>>,
+}
+
+impl CommitInfo {
+    /// The file's path at this commit, or `file_path` when it isn't known.
+    pub fn path_or<'a>(&'a self, file_path: &'a str) -> &'a str {
+        if self.path.is_empty() {
+            file_path
+        } else {
+            &self.path
+        }
+    }
+}
+
+/// Commits that touched `start_line..=end_line` of `file_path`, newest first.
+pub fn get_line_history(backend: &dyn GitBackend, file_path: &str, start_line: usize, end_line: usize) -> Result<Vec<CommitInfo>> {
+    let output_str = backend.log(&LogQuery::Lines { path: file_path, start_line, end_line })?;
+    let mut commits = parse_log_output(&output_str);
+    resolve_paths(backend, &mut commits, file_path)?;
+    Ok(commits)
+}
+
+/// Commits that touched any of `ranges` of `file_path`, newest first. Each commit's
+/// `line_ranges` has an entry for every range, including ones it didn't touch.
+pub fn get_line_history_for_ranges(backend: &dyn GitBackend, file_path: &str, ranges: &[(usize, usize)]) -> Result<Vec<CommitInfo>> {
+    if let [(start_line, end_line)] = *ranges {
+        return get_line_history(backend, file_path, start_line, end_line);
+    }
+
+    // Where each range sat in the commits that touched it, straight from git
+    let mut commits: Vec<CommitInfo> = Vec::new();
+    let mut touched: HashMap<(String, usize), Option<(usize, usize)>> = HashMap::new();
+    for (index, &(start_line, end_line)) in ranges.iter().enumerate() {
+        for commit in get_line_history(backend, file_path, start_line, end_line)? {
+            if let Some(&range) = commit.line_ranges.first() {
+                touched.insert((commit.hash.clone(), index), range);
+            }
+            if !commits.iter().any(|known| known.hash == commit.hash) {
+                commits.push(commit);
+            }
+        }
+    }
+    // Put them in the order git lists the file's history in, which committer times can't
+    // settle alone: they only count whole seconds, so commits made together tie
+    let order: HashMap<String, usize> = get_file_history(backend, file_path)?
+        .into_iter()
+        .enumerate()
+        .map(|(position, commit)| (commit.hash, position))
+        .collect();
+    commits.sort_by_key(|commit| order.get(&commit.hash).copied().unwrap_or(usize::MAX));
+
+    // git only reports a range in commits that touched it, so carry the others back from
+    // the next newer version, starting with the ranges as given in HEAD
+    let mut newer_contents = get_file_content(backend, "HEAD", file_path)?;
+    let mut newer_ranges: Vec<Option<(usize, usize)>> = ranges.iter().copied().map(Some).collect();
+    for commit in &mut commits {
+        let contents = get_file_content(backend, &commit.hash, commit.path_or(file_path))?;
+        let ops = diff_lines(&contents, &newer_contents);
+        commit.line_ranges = newer_ranges.iter()
+            .enumerate()
+            .map(|(index, newer)| match touched.get(&(commit.hash.clone(), index)) {
+                Some(range) => *range,
+                None => newer.and_then(|(start_line, end_line)| map_range_to_old(&ops, start_line, end_line).1),
+            })
+            .collect();
+        newer_ranges = commit.line_ranges.clone();
+        newer_contents = contents;
+    }
+    Ok(commits)
+}
+
+/// Every commit that touched `file_path`, following renames, newest first.
+pub fn get_file_history(backend: &dyn GitBackend, file_path: &str) -> Result<Vec<CommitInfo>> {
+    let output_str = backend.log(&LogQuery::File { path: file_path })?;
+    let mut commits = parse_log_output(&output_str);
+    resolve_paths(backend, &mut commits, file_path)?;
+    Ok(commits)
+}
+
+/// Commits that changed how many times `pattern` occurs in `file_path`, following renames,
+/// newest first: where it appeared, vanished, or was added or removed somewhere else in
+/// the file. `pattern` is a literal string unless `regex` is set.
+pub fn get_pickaxe_history(backend: &dyn GitBackend, file_path: &str, pattern: &str, regex: bool) -> Result<Vec<CommitInfo>> {
+    let output_str = backend.log(&LogQuery::Pickaxe { path: file_path, pattern, regex })?;
+    let mut commits = parse_log_output(&output_str);
+    resolve_paths(backend, &mut commits, file_path)?;
+    Ok(commits)
+}
+
+/// A tag on one of the repository's commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    /// Full hash of the commit tagged, through any annotated tag.
+    pub commit: String,
+    /// When that commit was made.
+    pub time: DateTime<FixedOffset>,
+}
+
+/// Every tag on a commit, oldest commit first.
+pub fn get_tags(backend: &dyn GitBackend) -> Result<Vec<Tag>> {
+    let output_str = backend.tags()?;
+    let mut tags: Vec<Tag> = output_str.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let (commit, seconds, name) = (fields.next()?, fields.next()?, fields.next()?);
+            Some(Tag { name: name.to_string(), commit: commit.to_string(), time: from_git(seconds.parse().ok()?, "+0000")? })
+        })
+        .collect();
+    tags.sort_by_key(|tag| tag.time);
+    Ok(tags)
+}
+
+/// The commit `rev` names: a hash or a prefix of one, a tag, a branch, or anything else
+/// git understands as a revision.
+pub fn get_commit(backend: &dyn GitBackend, rev: &str) -> Result<CommitInfo> {
+    let output_str = backend.log(&LogQuery::Commit { rev })?;
+    parse_log_output(&output_str).into_iter().next().ok_or_else(|| Error::UnknownRevision(rev.to_string()))
+}
+
+/// Turn the repository-relative paths git reported into paths relative to the current
+/// directory, keeping `file_path` exactly as given wherever the file still had that name.
+fn resolve_paths(backend: &dyn GitBackend, commits: &mut [CommitInfo], file_path: &str) -> Result<()> {
+    if commits.iter().all(|commit| commit.path.is_empty()) {
+        return Ok(());
+    }
+
+    let prefix = backend.prefix()?;
+    let current: Vec<&str> = prefix.split('/').filter(|part| !part.is_empty()).collect();
+    let requested = normalize(&format!("{}{}", prefix, file_path));
+
+    for commit in commits.iter_mut().filter(|commit| !commit.path.is_empty()) {
+        if commit.path == requested {
+            commit.path = file_path.to_string();
+            continue;
+        }
+        // Climb out of the current directory as far as the two paths differ
+        let target: Vec<&str> = commit.path.split('/').collect();
+        let shared = current.iter().zip(&target).take_while(|(a, b)| a == b).count();
+        let mut parts = vec![".."; current.len() - shared];
+        parts.extend(&target[shared..]);
+        commit.path = parts.join("/");
+    }
+    Ok(())
+}
+
+/// `path` with `.` and `..` components resolved and `/` separators.
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Every commit in the output of a log query in [`LOG_FORMAT`](crate::backend::LOG_FORMAT).
+pub fn parse_log_output(output: &str) -> Vec<CommitInfo> {
+    output.split('\x1e').filter_map(parse_commit_record).collect()
+}
+
+/// One commit in [`LOG_FORMAT`](crate::backend::LOG_FORMAT), without its leading record separator.
+pub fn parse_commit_record(record: &str) -> Option<CommitInfo> {
+    // Nine NUL-terminated fields, then whatever git printed after them
+    let fields: Vec<&str> = record.splitn(10, '\0').collect();
+    let [hash, parents, author, author_email, author_time, committer, committer_email, committer_time, message, rest] =
+        fields.try_into().ok()?;
+    let time = parse_git_date(author_time)?;
+    let (subject, body) = split_message(message);
+
+    Some(CommitInfo {
+        hash: hash.trim().to_string(),
+        date: short_date(&time),
+        time,
+        author: author.to_string(),
+        author_email: author_email.to_string(),
+        committer: committer.to_string(),
+        committer_email: committer_email.to_string(),
+        committer_time: parse_git_date(committer_time).unwrap_or(time),
+        parents: parents.split_whitespace().map(|parent| parent.to_string()).collect(),
+        message: subject,
+        trailers: parse_trailers(&body),
+        body,
+        path: trailing_path(rest).unwrap_or_default(),
+        line_ranges: hunk_range(rest).into_iter().collect(),
+    })
+}
+
+/// The new side of the first hunk header in a `git log -L` patch: where the traced
+/// range sat once the commit was applied, or `Some(None)` when the commit removed all
+/// of it. `None` when there is no patch.
+fn hunk_range(rest: &str) -> Option<Option<(usize, usize)>> {
+    let header = rest.lines().find(|line| line.starts_with("@@ -"))?;
+    let new_side = header.split_whitespace().find_map(|part| part.strip_prefix('+'))?;
+    let (start, count) = match new_side.split_once(',') {
+        Some((start, count)) => (start.parse().ok()?, count.parse().ok()?),
+        None => (new_side.parse().ok()?, 1),
+    };
+    Some((count > 0).then(|| (start, start + count - 1)))
+}
+
+/// The file's repository-relative path in what git printed after a record's fields:
+/// a `-z --name-status` entry, whose last path is the file's name after the commit,
+/// or the `+++ b/` line of the patch `git log -L` prints.
+fn trailing_path(rest: &str) -> Option<String> {
+    if let Some(path) = rest.lines().find_map(|line| line.strip_prefix("+++ b/")) {
+        return Some(path.to_string());
+    }
+
+    // Only NUL-terminated fields belong to the entry; a hunk header may follow them
+    let (entry, _) = rest.rsplit_once('\0')?;
+    let fields: Vec<&str> = entry.split('\0')
+        .map(|field| field.trim_start_matches('\n'))
+        .filter(|field| !field.is_empty())
+        .collect();
+    match fields.as_slice() {
+        [status, .., path] if status.starts_with(|c: char| c.is_ascii_uppercase()) => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+/// The subject (first paragraph joined onto one line, as `%s` prints it) and the rest.
+fn split_message(message: &str) -> (String, String) {
+    let message = message.trim();
+    let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+    let subject: Vec<&str> = subject.lines().map(|line| line.trim()).collect();
+    (subject.join(" "), body.trim().to_string())
+}
+
+/// Trailers in the last paragraph of `body`, which only counts when every line is one
+/// (indented lines continue the trailer above them).
+fn parse_trailers(body: &str) -> Vec<(String, String)> {
+    let Some(paragraph) = body.rsplit("\n\n").next().filter(|paragraph| !paragraph.trim().is_empty()) else {
+        return Vec::new();
+    };
+
+    let mut trailers: Vec<(String, String)> = Vec::new();
+    for line in paragraph.lines() {
+        if line.starts_with([' ', '\t']) {
+            match trailers.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    continue;
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            return Vec::new();
+        };
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Vec::new();
+        }
+        trailers.push((key.to_string(), value.trim().to_string()));
+    }
+    trailers
+}
+
+/// Blame `file_path` as of `commit_hash`, with syntax highlighting applied to every line.
+pub fn get_blame_for_commit(backend: &dyn GitBackend, commit_hash: &str, file_path: &str) -> Result<Vec<BlameLine>> {
+    let output_str = backend.blame(commit_hash, file_path)?;
+    Ok(parse_blame_output_with_highlighting(&output_str, file_path))
+}
+
+/// Lines added or removed by `commit_hash` within `start_line..=end_line` of `file_path`.
+pub fn get_commit_changes(backend: &dyn GitBackend, commit_hash: &str, file_path: &str, start_line: usize, end_line: usize) -> Result<Vec<LineChange>> {
+    let output_str = backend.show(commit_hash, file_path)?;
+    Ok(parse_diff_output(&output_str)
+        .into_iter()
+        .filter(|change| change.line_number >= start_line && change.line_number <= end_line)
+        .collect())
+}
+
+/// The hunks of `commit_hash`'s change to `file_path`, context included, with syntax
+/// highlighting applied to every line.
+pub fn get_commit_diff(backend: &dyn GitBackend, commit_hash: &str, file_path: &str) -> Result<Vec<DiffHunk>> {
+    let mut hunks = parse_diff_hunks(&backend.show(commit_hash, file_path)?);
+
+    // Each side is highlighted as one run of lines, so the highlighter sees them in order
+    for side in [ChangeType::Removed, ChangeType::Added] {
+        let mut lines: Vec<_> = hunks.iter_mut()
+            .flat_map(|hunk| hunk.lines.iter_mut())
+            .filter(|line| line.change_type.is_none_or(|change_type| change_type == side))
+            .collect();
+        let contents: Vec<&str> = lines.iter().map(|line| line.content.as_str()).collect();
+        let highlighted = highlight_lines(&contents, file_path);
+        for (line, highlighted_content) in lines.iter_mut().zip(highlighted) {
+            line.highlighted_content = highlighted_content;
+        }
+    }
+    Ok(hunks)
+}
+
+/// Contents of `file_path` as of `commit_hash`.
+pub fn get_file_content(backend: &dyn GitBackend, commit_hash: &str, file_path: &str) -> Result<String> {
+    backend.cat_file(commit_hash, file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MemoryBackend, MemoryCommit};
+
+    #[test]
+    fn parses_a_commit_record() {
+        let record = "abc123\0p1 p2\0Alice Smith\0alice@example.com\x002024-01-15T14:30:22+01:00\0\
+                      Bob Jones\0bob@example.com\x002024-01-16T10:00:00+00:00\0\
+                      Rename the parser\nacross two lines\n\nIt reads better.\n\nSigned-off-by: Alice Smith <alice@example.com>\n\0\
+                      \nR100\0src/old.rs\0src/new.rs\0";
+        let commit = parse_commit_record(record).unwrap();
+
+        assert_eq!(commit.hash, "abc123");
+        assert_eq!(commit.parents, ["p1", "p2"]);
+        assert_eq!(commit.author, "Alice Smith");
+        assert_eq!(commit.date, "2024-01-15");
+        assert_eq!(commit.time.to_rfc3339(), "2024-01-15T14:30:22+01:00");
+        assert_eq!(commit.committer, "Bob Jones");
+        assert_eq!(commit.committer_time.to_rfc3339(), "2024-01-16T10:00:00+00:00");
+        assert_eq!(commit.message, "Rename the parser across two lines");
+        assert_eq!(commit.body, "It reads better.\n\nSigned-off-by: Alice Smith <alice@example.com>");
+        assert_eq!(commit.trailers, [("Signed-off-by".to_string(), "Alice Smith <alice@example.com>".to_string())]);
+        assert_eq!(commit.path, "src/new.rs");
+        assert!(commit.line_ranges.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_truncated_record() {
+        assert!(parse_commit_record("abc123\0p1\0Alice Smith").is_none());
+    }
+
+    #[test]
+    fn parses_trailers_only_from_a_final_paragraph_of_them() {
+        let body = "Details.\n\nReviewed-by: Bob Jones\nCo-authored-by: Carol\n  White <carol@example.com>";
+        assert_eq!(parse_trailers(body), [
+            ("Reviewed-by".to_string(), "Bob Jones".to_string()),
+            ("Co-authored-by".to_string(), "Carol White <carol@example.com>".to_string()),
+        ]);
+
+        assert!(parse_trailers("").is_empty());
+        assert!(parse_trailers("Reviewed-by: Bob Jones\n\nJust prose at the end.").is_empty());
+        assert!(parse_trailers("Reviewed-by: Bob Jones\nand a line that isn't one").is_empty());
+        assert!(parse_trailers("Not a key: value").is_empty());
+    }
+
+    #[test]
+    fn reads_the_new_side_of_a_hunk_header() {
+        assert_eq!(hunk_range("\ndiff --git a/f b/f\n@@ -10,3 +12,4 @@\n"), Some(Some((12, 15))));
+        assert_eq!(hunk_range("@@ -7 +7 @@ fn main()"), Some(Some((7, 7))));
+        assert_eq!(hunk_range("@@ -3,2 +2,0 @@"), Some(None));
+        assert_eq!(hunk_range("\nM\0src/main.rs\0"), None);
+    }
+
+    #[test]
+    fn names_the_file_as_it_was_before_a_rename() {
+        let mut repo = MemoryBackend::new();
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("old.txt", "one\ntwo\n"));
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_100, "Rename").delete("old.txt").write("new.txt", "one\ntwo\n"));
+        repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_200, "Edit").write("new.txt", "one\nTWO\n"));
+
+        let commits = get_file_history(&repo, "new.txt").unwrap();
+        let paths: Vec<&str> = commits.iter().map(|commit| commit.path_or("new.txt")).collect();
+        assert_eq!(paths, ["new.txt", "new.txt", "old.txt"]);
+
+        let contents = get_file_content(&repo, &commits[2].hash, commits[2].path_or("new.txt")).unwrap();
+        assert_eq!(contents, "one\ntwo\n");
+    }
+
+    #[test]
+    fn keeps_every_range_in_line_history_for_several() {
+        let mut repo = MemoryBackend::new();
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.txt", "a\nb\nc\nd\ne\nf\n"));
+        repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_100, "Shout b").write("f.txt", "a\nB\nc\nd\ne\nf\n"));
+        repo.commit(MemoryCommit::new("Carol White", 1_700_000_200, "Shout f").write("f.txt", "z\na\nB\nc\nd\ne\nF\n"));
+
+        let commits = get_line_history_for_ranges(&repo, "f.txt", &[(3, 3), (6, 7)]).unwrap();
+        let messages: Vec<&str> = commits.iter().map(|commit| commit.message.as_str()).collect();
+        assert_eq!(messages, ["Shout f", "Shout b", "Add"]);
+
+        // Each commit says where both ranges were, whether it touched them or not
+        let ranges: Vec<&[Option<(usize, usize)>]> = commits.iter().map(|commit| commit.line_ranges.as_slice()).collect();
+        assert_eq!(ranges, [
+            &[Some((3, 3)), Some((6, 7))][..],
+            &[Some((2, 2)), Some((5, 6))][..],
+            &[Some((2, 2)), Some((5, 6))][..],
+        ]);
+    }
+
+    #[test]
+    fn keeps_git_order_for_ranges_changed_in_the_same_second() {
+        let mut repo = MemoryBackend::new();
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("g.rs", "one\ntwo\nthree\nfour\nfive\n"));
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Five").write("g.rs", "one\ntwo\nthree\nfour\nFIVE\n"));
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Two").write("g.rs", "one\nTWO\nthree\nfour\nFIVE\n"));
+
+        let commits = get_line_history_for_ranges(&repo, "g.rs", &[(2, 3), (5, 5)]).unwrap();
+        let messages: Vec<&str> = commits.iter().map(|commit| commit.message.as_str()).collect();
+        assert_eq!(messages, ["Two", "Five", "Add"]);
+
+        let ranges: Vec<&[Option<(usize, usize)>]> = commits.iter().map(|commit| commit.line_ranges.as_slice()).collect();
+        assert_eq!(ranges, [&[Some((2, 3)), Some((5, 5))][..], &[Some((2, 3)), Some((5, 5))][..], &[Some((2, 3)), Some((5, 5))][..]]);
+    }
+}
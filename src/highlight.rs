@@ -0,0 +1,45 @@
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+use std::sync::OnceLock;
+
+static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+
+/// Pre-render syntax highlighting for consecutive `lines` of `file_path` as terminal escapes.
+pub fn highlight_lines(lines: &[&str], file_path: &str) -> Vec<String> {
+    // Syntax and theme sets are expensive to load, so share them across every version
+    let ps = SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines);
+    let ts = THEME_SET.get_or_init(ThemeSet::load_defaults);
+
+    // Try to determine syntax by file extension
+    let syntax = ps.find_syntax_for_file(file_path)
+        .unwrap_or(None)
+        .unwrap_or_else(|| ps.find_syntax_plain_text());
+
+    // Use a dark theme
+    let theme = &ts.themes["base16-ocean.dark"];
+    let mut h = HighlightLines::new(syntax, theme);
+
+    lines.iter().map(|&content| {
+        if content.len() > 200 || content.trim().is_empty() {
+            // For very long lines or empty lines, skip highlighting for performance/stability
+            return content.to_string();
+        }
+
+        // Try to apply syntax highlighting, fallback to plain text on any error
+        match h.highlight_line(content, ps) {
+            Ok(ranges) => {
+                let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
+                // Ensure the highlighted content ends with a reset sequence
+                if escaped.contains('\x1b') && !escaped.ends_with("\x1b[0m") {
+                    format!("{}\x1b[0m", escaped)
+                } else {
+                    escaped
+                }
+            }
+            Err(_) => content.to_string(), // Fallback to plain text
+        }
+    }).collect()
+}
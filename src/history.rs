@@ -0,0 +1,295 @@
+use crate::backend::GitBackend;
+use crate::blame::{abbreviate_author, can_derive_blame, derive_blame, BlameLine};
+use crate::cache::BlameCache;
+use crate::error::{Error, Result};
+use crate::git::{get_blame_for_commit, get_file_content, get_file_history, get_line_history, CommitInfo};
+use crate::highlight::highlight_lines;
+use crate::pool::{default_jobs, map_ordered};
+
+/// A file as it existed at one commit, with per-line authorship.
+#[derive(Debug, Clone)]
+pub struct FileVersion {
+    pub commit_hash: String,
+    pub commit_date: String,
+    pub commit_message: String,
+    /// Everything git records about the commit, including the three fields above.
+    pub commit: CommitInfo,
+    pub blame_lines: Vec<BlameLine>,
+}
+
+impl FileVersion {
+    /// The version of a file at `commit`, with the given per-line authorship.
+    pub fn new(commit: &CommitInfo, blame_lines: Vec<BlameLine>) -> Self {
+        FileVersion {
+            commit_hash: commit.hash.clone(),
+            commit_date: commit.date.clone(),
+            commit_message: commit.message.clone(),
+            commit: commit.clone(),
+            blame_lines,
+        }
+    }
+
+    /// Whether `line` was last changed by this version's own commit.
+    pub fn changed_here(&self, line: &BlameLine) -> bool {
+        !line.commit_hash.is_empty() && self.commit_hash.starts_with(&line.commit_hash)
+    }
+}
+
+/// How each version is built when loading a file's history.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Blame every version. When off, contents are read with `cat-file` instead and every
+    /// line is attributed to the version's own commit, which is much faster for views
+    /// that only need the text.
+    pub blame: bool,
+    /// Derive each version's blame from the version before it plus the commit's diff, so
+    /// only changed lines are re-attributed. Applies to whole-file history; the oldest
+    /// version, merges, and versions whose parent had a different file than the version
+    /// before them (as when that came from another branch) get a full blame.
+    pub incremental: bool,
+    /// With `incremental`, run a full blame instead of deriving one every this many
+    /// versions. Stretches between these are independent, so they load in parallel.
+    /// `0` derives every version it can.
+    pub checkpoint_interval: usize,
+    /// Maximum number of versions loaded concurrently.
+    pub jobs: usize,
+    /// Where full blames are looked up before running `git blame`, and stored after.
+    pub cache: Option<BlameCache>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions { blame: true, incremental: true, checkpoint_interval: 50, jobs: default_jobs(), cache: None }
+    }
+}
+
+/// A commit whose version of the file couldn't be loaded, and why.
+#[derive(Debug, Clone)]
+pub struct SkippedVersion {
+    pub commit: CommitInfo,
+    pub error: Error,
+}
+
+/// The versions of a file that loaded, newest first, and the commits that didn't.
+#[derive(Debug, Clone, Default)]
+pub struct FileHistory {
+    pub versions: Vec<FileVersion>,
+    pub skipped: Vec<SkippedVersion>,
+}
+
+impl FileHistory {
+    /// Split per-commit outcomes, kept in order, into versions and skipped commits.
+    fn from_outcomes(outcomes: impl IntoIterator<Item = Outcome>) -> Self {
+        let mut history = FileHistory::default();
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Loaded(version) => history.versions.push(version),
+                Outcome::Skipped(skipped) => history.skipped.push(skipped),
+            }
+        }
+        history
+    }
+}
+
+/// What became of one commit's version while loading.
+enum Outcome {
+    Loaded(FileVersion),
+    Skipped(SkippedVersion),
+}
+
+/// Every version of `file_path`, newest first.
+pub fn get_file_versions(backend: &dyn GitBackend, file_path: &str) -> Result<FileHistory> {
+    get_file_versions_with(backend, file_path, &LoadOptions::default())
+}
+
+/// Every version of `file_path`, newest first, built according to `options`.
+pub fn get_file_versions_with(backend: &dyn GitBackend, file_path: &str, options: &LoadOptions) -> Result<FileHistory> {
+    let commits = get_file_history(backend, file_path)?;
+    if !(options.blame && options.incremental) {
+        return Ok(FileHistory::from_outcomes(load_versions(backend, commits, file_path, options)));
+    }
+
+    // Walk from the oldest version forwards so each one can build on its predecessor,
+    // starting every stretch between checkpoints from a full blame
+    let oldest_first: Vec<CommitInfo> = commits.into_iter().rev().collect();
+    let stretch = match options.checkpoint_interval {
+        0 => oldest_first.len().max(1),
+        interval => interval,
+    };
+    let stretches: Vec<Vec<CommitInfo>> = oldest_first.chunks(stretch).map(|chunk| chunk.to_vec()).collect();
+
+    let mut outcomes: Vec<Outcome> = map_ordered(stretches, options.jobs, |stretch| {
+        load_incremental_versions(backend, stretch, file_path, options)
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+
+    outcomes.reverse();
+    Ok(FileHistory::from_outcomes(outcomes))
+}
+
+/// Load consecutive versions (oldest first): a full blame for the first, then blame derived
+/// from the version before wherever the commit's parent still had it.
+fn load_incremental_versions(backend: &dyn GitBackend, commits: Vec<CommitInfo>, file_path: &str, options: &LoadOptions) -> Vec<Outcome> {
+    let mut outcomes: Vec<Outcome> = Vec::new();
+
+    for commit in commits {
+        let previous = match outcomes.last() {
+            Some(Outcome::Loaded(version))
+                if can_derive_blame(backend, &commit, &version.blame_lines, version.commit.path_or(file_path)) => Some(version),
+            _ => None,
+        };
+        let path = commit.path_or(file_path);
+        let derived = previous.and_then(|previous| {
+            get_file_content(backend, &commit.hash, path)
+                .ok()
+                .map(|contents| derive_blame(&previous.blame_lines, &contents, &commit, path))
+        });
+        let blame_lines = match derived {
+            Some(blame_lines) => blame_lines,
+            None => match full_blame(backend, &commit, file_path, options) {
+                Ok(blame_lines) => blame_lines,
+                Err(error) => {
+                    outcomes.push(Outcome::Skipped(SkippedVersion { commit, error }));
+                    continue;
+                }
+            },
+        };
+
+        outcomes.push(Outcome::Loaded(FileVersion::new(&commit, blame_lines)));
+    }
+
+    outcomes
+}
+
+/// Load every commit's version independently.
+fn load_versions(backend: &dyn GitBackend, commits: Vec<CommitInfo>, file_path: &str, options: &LoadOptions) -> Vec<Outcome> {
+    map_ordered(commits, options.jobs, |commit| match load_lines(backend, &commit, file_path, options) {
+        Ok(blame_lines) => Outcome::Loaded(FileVersion::new(&commit, blame_lines)),
+        Err(error) => Outcome::Skipped(SkippedVersion { commit, error }),
+    })
+}
+
+/// Versions of `file_path` from commits that touched `start_line..=end_line`, newest first.
+pub fn get_file_versions_for_lines(backend: &dyn GitBackend, file_path: &str, start_line: usize, end_line: usize) -> Result<FileHistory> {
+    get_file_versions_for_lines_with(backend, file_path, start_line, end_line, &LoadOptions::default())
+}
+
+/// Versions of `file_path` from commits that touched `start_line..=end_line`, newest first,
+/// built according to `options`.
+pub fn get_file_versions_for_lines_with(backend: &dyn GitBackend, file_path: &str, start_line: usize, end_line: usize, options: &LoadOptions) -> Result<FileHistory> {
+    // Get commits that touched the specific line range using git log -L
+    let commits = get_line_history(backend, file_path, start_line, end_line)?;
+    let mut history = FileHistory::from_outcomes(load_versions(backend, commits, file_path, options));
+
+    // Check if any of the specified lines exist in this commit, wherever they had moved to
+    history.versions.retain(|version| {
+        let Some((start_line, end_line)) = version.commit.line_ranges.first().copied().unwrap_or(Some((start_line, end_line))) else {
+            return false;
+        };
+        version.blame_lines.iter().any(|line| line.line_number >= start_line && line.line_number <= end_line)
+    });
+
+    Ok(history)
+}
+
+pub(crate) fn load_lines(backend: &dyn GitBackend, commit: &CommitInfo, file_path: &str, options: &LoadOptions) -> Result<Vec<BlameLine>> {
+    if options.blame {
+        full_blame(backend, commit, file_path, options)
+    } else {
+        get_plain_lines(backend, commit, file_path)
+    }
+}
+
+/// A full blame of `file_path` at `commit`, from `options.cache` when it has one.
+fn full_blame(backend: &dyn GitBackend, commit: &CommitInfo, file_path: &str, options: &LoadOptions) -> Result<Vec<BlameLine>> {
+    // Before a rename the file has to be looked up under its old name
+    let file_path = commit.path_or(file_path);
+    if let Some(blame_lines) = options.cache.as_ref().and_then(|cache| cache.get(&commit.hash, file_path)) {
+        return Ok(blame_lines);
+    }
+
+    let blame_lines = get_blame_for_commit(backend, &commit.hash, file_path)?;
+    if let Some(cache) = &options.cache {
+        // A cache that can't be written to only costs speed next time
+        let _ = cache.put(&commit.hash, file_path, &blame_lines);
+    }
+    Ok(blame_lines)
+}
+
+/// Lines of `file_path` at `commit` without blame, each attributed to `commit` itself.
+/// When `commit` knows the file's path at that point, that is used instead.
+pub fn get_plain_lines(backend: &dyn GitBackend, commit: &CommitInfo, file_path: &str) -> Result<Vec<BlameLine>> {
+    let file_path = commit.path_or(file_path);
+    let contents = get_file_content(backend, &commit.hash, file_path)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    let highlighted = highlight_lines(&lines, file_path);
+    let author = abbreviate_author(&commit.author);
+    let short_hash: String = commit.hash.chars().take(7).collect();
+
+    Ok(lines.iter()
+        .zip(highlighted)
+        .enumerate()
+        .map(|(index, (content, highlighted_content))| BlameLine {
+            line_number: index + 1,
+            author: author.clone(),
+            date: commit.date.clone(),
+            author_time: commit.time,
+            commit_hash: short_hash.clone(),
+            commit_message: commit.message.clone(),
+            content: content.to_string(),
+            highlighted_content,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{CountingBackend, MemoryBackend, MemoryCommit};
+    use crate::git::get_commit;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn blames_in_full_when_the_version_before_is_not_the_parent() {
+        let mut repo = MemoryBackend::new();
+        let first = repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.txt", "a\nb\n"));
+        repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_100, "Shout b").write("f.txt", "a\nB\n"));
+        let third = repo.commit(MemoryCommit::new("Carol White", 1_700_000_200, "Shout a").write("f.txt", "A\nB\n"));
+
+        // As after a merge, where the log puts a commit next to one from another branch
+        let commits = vec![get_commit(&repo, &first).unwrap(), get_commit(&repo, &third).unwrap()];
+        let outcomes = load_incremental_versions(&repo, commits, "f.txt", &LoadOptions::default());
+        let history = FileHistory::from_outcomes(outcomes);
+
+        let authors: Vec<&str> = history.versions[1].blame_lines.iter().map(|line| line.author.as_str()).collect();
+        assert_eq!(authors, ["Carol W.", "Bob J."]);
+    }
+
+    #[test]
+    fn incremental_loading_matches_full_blames() {
+        let mut repo = MemoryBackend::new();
+        for (index, contents) in ["a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\nd\n", "B\nc\nD\n"].iter().enumerate() {
+            let author = ["Alice Smith", "Bob Jones"][index % 2];
+            repo.commit(MemoryCommit::new(author, 1_700_000_000 + index as i64, "Edit").write("f.txt", contents));
+            // Other files change in between, so versions are rarely each other's parents
+            repo.commit(MemoryCommit::new("Carol White", 1_700_000_000 + index as i64, "Elsewhere").write("g.txt", contents));
+        }
+
+        let incremental = get_file_versions(&repo, "f.txt").unwrap();
+        let full = get_file_versions_with(&repo, "f.txt", &LoadOptions { incremental: false, ..LoadOptions::default() }).unwrap();
+        let attribution = |history: &FileHistory| -> Vec<Vec<(String, String)>> {
+            history.versions.iter()
+                .map(|version| version.blame_lines.iter().map(|line| (line.commit_hash.clone(), line.content.clone())).collect())
+                .collect()
+        };
+        assert_eq!(incremental.versions.len(), 4);
+        assert_eq!(attribution(&incremental), attribution(&full));
+
+        // Only the oldest version needed a full blame
+        let counting = CountingBackend::new(repo);
+        get_file_versions(&counting, "f.txt").unwrap();
+        assert_eq!(counting.blames.load(Ordering::Relaxed), 1);
+    }
+}
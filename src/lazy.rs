@@ -0,0 +1,380 @@
+//! A file's history whose versions are loaded on demand by background workers.
+//!
+//! The commit list is cheap to fetch, but blaming every version up front can take
+//! a long time on a file with thousands of commits. [`LazyVersions`] starts with
+//! just the commits and blames individual versions when they are asked for, so a
+//! viewer can open immediately and load whatever the user navigates to.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use crate::backend::GitBackend;
+use crate::blame::{can_derive_blame, derive_blame};
+use crate::diff::DiffHunk;
+use crate::error::{Error, Result};
+use crate::git::{get_commit_diff, get_file_content, CommitInfo};
+use crate::history::{load_lines, FileVersion, LoadOptions, SkippedVersion};
+
+/// How far along loading one version is.
+#[derive(Debug, Clone)]
+pub enum VersionState {
+    /// Not asked for yet.
+    Pending,
+    /// Queued or being loaded by a worker.
+    Loading,
+    Ready(Arc<FileVersion>),
+    Failed(Error),
+}
+
+/// Every version of a file, newest first, each loaded the first time it is requested.
+///
+/// Requests are served by up to `LoadOptions::jobs` worker threads. Explicit
+/// [`request`](LazyVersions::request)s jump the queue, while
+/// [`prefetch`](LazyVersions::prefetch)es wait behind them.
+pub struct LazyVersions {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    backend: Arc<dyn GitBackend>,
+    file_path: String,
+    commits: Vec<CommitInfo>,
+    options: LoadOptions,
+    /// Whether `commits` is the file's complete history, so blame can be derived from the
+    /// neighbouring version wherever the commit's parent still had it.
+    consecutive: bool,
+    state: Mutex<Queue>,
+    /// Signalled when work is queued or the history is dropped.
+    work: Condvar,
+    /// Signalled when a version finishes loading.
+    done: Condvar,
+    generation: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+struct Queue {
+    versions: Vec<VersionState>,
+    pending: VecDeque<usize>,
+    /// Diffs asked for by index, `None` while they are still loading.
+    diffs: HashMap<usize, Option<Result<Arc<Vec<DiffHunk>>>>>,
+}
+
+impl LazyVersions {
+    /// Versions of `file_path` at `commits` (newest first, as from `get_file_history`).
+    ///
+    /// Set `consecutive` when `commits` is the file's complete history, so a version's
+    /// blame can be derived from its predecessor's wherever its commit's parent still had
+    /// that version. A predecessor that is queued too is loaded first, and one already
+    /// being loaded is waited for.
+    pub fn new(backend: Arc<dyn GitBackend>, file_path: &str, commits: Vec<CommitInfo>, consecutive: bool, options: &LoadOptions) -> Self {
+        let count = commits.len();
+        let shared = Arc::new(Shared {
+            backend,
+            file_path: file_path.to_string(),
+            commits,
+            options: options.clone(),
+            consecutive,
+            state: Mutex::new(Queue { versions: vec![VersionState::Pending; count], pending: VecDeque::new(), diffs: HashMap::new() }),
+            work: Condvar::new(),
+            done: Condvar::new(),
+            generation: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+
+        for _ in 0..options.jobs.max(1).min(count) {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || shared.work());
+        }
+
+        LazyVersions { shared }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.commits.is_empty()
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.shared.file_path
+    }
+
+    /// Where the versions are loaded from, for anything else to be asked about them.
+    pub fn backend(&self) -> &dyn GitBackend {
+        &*self.shared.backend
+    }
+
+    /// The commit behind version `index`, available whether or not the version is loaded.
+    pub fn commit(&self, index: usize) -> &CommitInfo {
+        &self.shared.commits[index]
+    }
+
+    pub fn state(&self, index: usize) -> VersionState {
+        self.shared.lock().versions[index].clone()
+    }
+
+    /// Version `index` if it has finished loading.
+    pub fn get(&self, index: usize) -> Option<Arc<FileVersion>> {
+        match &self.shared.lock().versions[index] {
+            VersionState::Ready(version) => Some(Arc::clone(version)),
+            _ => None,
+        }
+    }
+
+    /// Load version `index` ahead of anything that is only being prefetched.
+    pub fn request(&self, index: usize) {
+        self.enqueue(index, true);
+    }
+
+    /// Load version `index` once the workers have nothing more urgent to do.
+    pub fn prefetch(&self, index: usize) {
+        self.enqueue(index, false);
+    }
+
+    /// Request version `index` and block until it has loaded or failed.
+    pub fn wait(&self, index: usize) -> Result<Arc<FileVersion>> {
+        self.request(index);
+        let mut queue = self.shared.lock();
+        loop {
+            match &queue.versions[index] {
+                VersionState::Ready(version) => return Ok(Arc::clone(version)),
+                VersionState::Failed(e) => return Err(e.clone()),
+                _ => queue = self.shared.done.wait(queue).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            }
+        }
+    }
+
+    /// The change version `index`'s commit made to the file, once it has loaded. The first
+    /// call starts loading it in the background, and [`generation`](LazyVersions::generation)
+    /// changes when it is done.
+    pub fn diff(&self, index: usize) -> Option<Result<Arc<Vec<DiffHunk>>>> {
+        let mut queue = self.shared.lock();
+        if let Some(diff) = queue.diffs.get(&index) {
+            return diff.clone();
+        }
+        queue.diffs.insert(index, None);
+
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || {
+            let commit = &shared.commits[index];
+            let diff = get_commit_diff(&*shared.backend, &commit.hash, commit.path_or(&shared.file_path));
+            shared.lock().diffs.insert(index, Some(diff.map(Arc::new)));
+            shared.generation.fetch_add(1, Ordering::AcqRel);
+        });
+        None
+    }
+
+    /// Versions that failed to load so far, newest first, so they can be reported
+    /// instead of silently going missing.
+    pub fn skipped(&self) -> Vec<SkippedVersion> {
+        let queue = self.shared.lock();
+        queue.versions.iter()
+            .zip(&self.shared.commits)
+            .filter_map(|(state, commit)| match state {
+                VersionState::Failed(error) => Some(SkippedVersion { commit: commit.clone(), error: error.clone() }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A counter that changes every time a version or diff finishes loading, so callers can
+    /// tell when there is something new to show.
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::Acquire)
+    }
+
+    fn enqueue(&self, index: usize, urgent: bool) {
+        if index >= self.len() {
+            return;
+        }
+        let mut queue = self.shared.lock();
+        match queue.versions[index] {
+            VersionState::Pending => queue.versions[index] = VersionState::Loading,
+            // Already queued: an urgent request still moves it to the front
+            VersionState::Loading if urgent => match queue.pending.iter().position(|&queued| queued == index) {
+                Some(position) => {
+                    queue.pending.remove(position);
+                }
+                None => return, // A worker is already on it
+            },
+            _ => return,
+        }
+
+        if urgent {
+            queue.pending.push_front(index);
+        } else {
+            queue.pending.push_back(index);
+        }
+        self.shared.work.notify_one();
+    }
+}
+
+impl Drop for LazyVersions {
+    fn drop(&mut self) {
+        // Workers finish whatever they are loading and then exit; nobody waits for them
+        self.shared.shutdown.store(true, Ordering::Release);
+        let _queue = self.shared.lock();
+        self.shared.work.notify_all();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn work(&self) {
+        loop {
+            let index = {
+                let mut queue = self.lock();
+                loop {
+                    if self.shutdown.load(Ordering::Acquire) {
+                        return;
+                    }
+                    match queue.pending.pop_front() {
+                        Some(index) => break self.oldest_queued_predecessor(&mut queue, index),
+                        None => queue = self.work.wait(queue).unwrap_or_else(|poisoned| poisoned.into_inner()),
+                    }
+                }
+            };
+
+            let state = match self.load(index) {
+                Ok(version) => VersionState::Ready(Arc::new(version)),
+                Err(e) => VersionState::Failed(e),
+            };
+            self.lock().versions[index] = state;
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            self.done.notify_all();
+        }
+    }
+
+    /// Whether version `index` would be derived from the one before it, which is the next
+    /// index since commits are newest first.
+    fn derives(&self, index: usize) -> bool {
+        // Checkpoints fall where they would when loading eagerly, counting from the oldest version
+        let position = self.commits.len() - 1 - index;
+        let checkpoint = self.options.checkpoint_interval != 0 && position.is_multiple_of(self.options.checkpoint_interval);
+        self.options.blame && self.options.incremental && self.consecutive && !checkpoint && index + 1 < self.commits.len()
+    }
+
+    /// The version to load in place of `index`, just taken off the queue: the oldest of the
+    /// run of queued versions before it, with the rest put back to follow in order.
+    fn oldest_queued_predecessor(&self, queue: &mut Queue, mut index: usize) -> usize {
+        while self.derives(index) {
+            let Some(position) = queue.pending.iter().position(|&queued| queued == index + 1) else {
+                break;
+            };
+            queue.pending.remove(position);
+            queue.pending.push_front(index);
+            index += 1;
+        }
+        index
+    }
+
+    /// The loaded version before `index`, waiting for it when another worker is loading it.
+    fn predecessor(&self, index: usize) -> Option<Arc<FileVersion>> {
+        let mut queue = self.lock();
+        loop {
+            match &queue.versions[index + 1] {
+                VersionState::Ready(previous) => return Some(Arc::clone(previous)),
+                // Still queued, so waiting could mean waiting on this very worker
+                VersionState::Loading if !queue.pending.contains(&(index + 1)) => {
+                    queue = self.done.wait(queue).unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn load(&self, index: usize) -> Result<FileVersion> {
+        let backend = &*self.backend;
+        let commit = &self.commits[index];
+
+        let previous = if self.derives(index) { self.predecessor(index) } else { None };
+        let previous = previous.filter(|previous| {
+            can_derive_blame(backend, commit, &previous.blame_lines, previous.commit.path_or(&self.file_path))
+        });
+
+        if let Some(previous) = previous {
+            let path = commit.path_or(&self.file_path);
+            if let Ok(contents) = get_file_content(backend, &commit.hash, path) {
+                let blame_lines = derive_blame(&previous.blame_lines, &contents, commit, path);
+                return Ok(FileVersion::new(commit, blame_lines));
+            }
+        }
+
+        let blame_lines = load_lines(backend, commit, &self.file_path, &self.options)?;
+        Ok(FileVersion::new(commit, blame_lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{CountingBackend, MemoryBackend, MemoryCommit};
+    use crate::diff::ChangeType;
+    use crate::git::get_file_history;
+    use crate::history::get_file_versions;
+
+    #[test]
+    fn derives_blame_from_neighbours_loaded_on_demand() {
+        let mut repo = MemoryBackend::new();
+        for (index, contents) in ["a\nb\n", "a\nB\n", "A\nB\n", "A\nB\nc\n"].iter().enumerate() {
+            repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000 + index as i64, "Edit").write("f.txt", contents));
+            repo.commit(MemoryCommit::new("Bob Jones", 1_700_000_000 + index as i64, "Elsewhere").write("g.txt", contents));
+        }
+        let eager = get_file_versions(&repo, "f.txt").unwrap();
+        let commits = get_file_history(&repo, "f.txt").unwrap();
+        let backend = Arc::new(CountingBackend::new(repo));
+        let lines = |version: &FileVersion| -> Vec<(String, String)> {
+            version.blame_lines.iter().map(|line| (line.commit_hash.clone(), line.content.clone())).collect()
+        };
+
+        // One worker loads older versions first; with more, they wait for each other
+        for jobs in [1, 4] {
+            backend.blames.store(0, Ordering::Relaxed);
+            let options = LoadOptions { jobs, ..LoadOptions::default() };
+            let versions = LazyVersions::new(backend.clone(), "f.txt", commits.clone(), true, &options);
+
+            // Queue every version newest first before the workers get to any of them
+            {
+                let mut queue = versions.shared.lock();
+                for index in 0..versions.len() {
+                    queue.versions[index] = VersionState::Loading;
+                    queue.pending.push_back(index);
+                }
+            }
+            versions.shared.work.notify_all();
+
+            for (index, expected) in eager.versions.iter().enumerate() {
+                assert_eq!(lines(&versions.wait(index).unwrap()), lines(expected));
+            }
+            assert_eq!(backend.blames.load(Ordering::Relaxed), 1, "with {} jobs", jobs);
+        }
+    }
+
+    #[test]
+    fn loads_diffs_in_the_background() {
+        let mut repo = MemoryBackend::new();
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_000, "Add").write("f.txt", "a\nb\n"));
+        repo.commit(MemoryCommit::new("Alice Smith", 1_700_000_100, "Edit").write("f.txt", "a\nB\n"));
+        let commits = get_file_history(&repo, "f.txt").unwrap();
+        let versions = LazyVersions::new(Arc::new(repo), "f.txt", commits, true, &LoadOptions::default());
+
+        let generation = versions.generation();
+        assert!(versions.diff(0).is_none());
+        while versions.generation() == generation {
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let hunks = versions.diff(0).unwrap().unwrap();
+        let changes: Vec<(Option<ChangeType>, &str)> = hunks.iter()
+            .flat_map(|hunk| &hunk.lines)
+            .map(|line| (line.change_type, line.content.as_str()))
+            .collect();
+        assert_eq!(changes, [(None, "a"), (Some(ChangeType::Removed), "b"), (Some(ChangeType::Added), "B")]);
+    }
+}
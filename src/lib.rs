@@ -0,0 +1,50 @@
+//! Git Archaeology - the history engine behind the `garch` command.
+//!
+//! Loads every version of a file (or of a line range within it) together with
+//! per-line blame, so that other tools can walk a file's timeline without
+//! shelling out to the `garch` binary. All git access goes through a
+//! [`GitBackend`]: [`ProcessBackend`] runs the `git` binary, while
+//! [`MemoryBackend`] is an in-memory repository for tests. With the `gix`
+//! cargo feature, `backend::GixBackend` reads everything in-process.
+//!
+//! ```no_run
+//! let backend = garch::ProcessBackend::new();
+//! let history = garch::get_file_versions(&backend, "src/main.rs")?;
+//! for version in &history.versions {
+//!     println!("{} {} ({} lines)", version.commit_hash, version.commit_message, version.blame_lines.len());
+//! }
+//! for skipped in &history.skipped {
+//!     eprintln!("skipped {}: {}", skipped.commit.hash, skipped.error);
+//! }
+//! # Ok::<(), garch::Error>(())
+//! ```
+
+pub mod backend;
+pub mod blame;
+pub mod cache;
+pub mod date;
+pub mod diff;
+pub mod error;
+pub mod git;
+pub mod highlight;
+pub mod history;
+pub mod lazy;
+pub mod pool;
+pub mod range;
+
+pub use backend::{GitBackend, MemoryBackend, ProcessBackend};
+pub use blame::BlameLine;
+pub use cache::BlameCache;
+pub use date::{DateFormat, DisplayZone};
+pub use diff::{AlignedRow, ChangeType, DiffHunk, DiffLine, LineChange, WordDiff};
+pub use error::Error;
+pub use git::{
+    get_blame_for_commit, get_commit, get_commit_changes, get_commit_diff, get_file_content, get_file_history, get_line_history,
+    get_line_history_for_ranges, get_pickaxe_history, get_tags, CommitInfo, Tag,
+};
+pub use history::{
+    get_file_versions, get_file_versions_for_lines, get_file_versions_for_lines_with, get_file_versions_with, FileHistory,
+    FileVersion, LoadOptions, SkippedVersion,
+};
+pub use lazy::{LazyVersions, VersionState};
+pub use range::{parse_file_range, LineTarget, PatternEnd};
@@ -1,57 +1,61 @@
 use clap::{Arg, Command};
-use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind, MouseEventKind},
-    execute,
-    style::{Color, ResetColor, SetForegroundColor, SetBackgroundColor},
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+use garch::pool::default_jobs;
+use garch::{
+    get_file_history, get_line_history_for_ranges, get_pickaxe_history, parse_file_range, BlameCache, DateFormat, DisplayZone, GitBackend, LazyVersions,
+    LoadOptions, ProcessBackend,
 };
-use std::io::{self, Write};
-use std::process::Command as ProcessCommand;
-use std::str;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
-use syntect::util::as_24_bit_terminal_escaped;
-
-#[derive(Debug)]
-struct CommitInfo {
-    hash: String,
-    date: String,
-    author: String,
-    message: String,
-}
-
-#[derive(Debug)]
-struct LineChange {
-    line_number: usize,
-    change_type: ChangeType,
-    content: String,
-}
-
-#[derive(Debug)]
-enum ChangeType {
-    Added,
-    Removed,
-    Modified,
-}
-
-#[derive(Debug, Clone)]
-struct BlameLine {
-    line_number: usize,
-    author: String,
-    date: String,
-    commit_hash: String,
-    commit_message: String,
-    content: String,
-    highlighted_content: String,
-}
-
-#[derive(Debug)]
-struct FileVersion {
-    commit_hash: String,
-    commit_date: String,
-    commit_message: String,
-    blame_lines: Vec<BlameLine>,
+use std::sync::Arc;
+
+mod viewer;
+
+use viewer::{run_interactive_viewer, ViewerOptions};
+
+/// Add the options shared by every subcommand that loads versions into the viewer.
+fn with_load_args(cmd: Command) -> Command {
+    cmd
+        .arg(
+            Arg::new("jobs")
+                .help("Number of versions to load in parallel")
+                .long_help("Maximum number of git operations to run at once while loading versions. \
+                           Defaults to the number of CPUs, capped at 8.")
+                .long("jobs")
+                .short('j')
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+        )
+        .arg(
+            Arg::new("plain")
+                .help("Show file contents only, without per-line authorship")
+                .long_help("Skip git blame and read each version with a single long-lived `git cat-file --batch` \
+                           process. Much faster on long histories when you only care about how the text changed.")
+                .long("plain")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("timezone")
+                .help("Timezone to show dates in: author, local or utc")
+                .long_help("Show commit and blame dates in the author's own timezone (as git does), this \
+                           machine's local timezone, or UTC. Press z in the viewer to switch.")
+                .long("timezone")
+                .value_name("ZONE")
+                .value_parser(["author", "local", "utc"])
+                .default_value("author")
+        )
+        .arg(
+            Arg::new("date")
+                .help("Date format: short, relative, iso, full or format:<pattern>")
+                .long_help("How dates are written in the header and author sections:\n  \
+                           short     2024-01-15\n  \
+                           relative  3 months ago\n  \
+                           iso       2024-01-15 14:30:22 +0100\n  \
+                           full      Mon Jan 15 14:30:22 2024 +0100\n  \
+                           format:%d %b %Y  (any strftime-style pattern)\n\
+                           Press d in the viewer to switch.")
+                .long("date")
+                .value_name("FORMAT")
+                .value_parser(DateFormat::parse)
+                .default_value("short")
+        )
 }
 
 fn main() {
@@ -61,7 +65,7 @@ fn main() {
         .long_about("garch transforms your git history into an interactive timeline, allowing you to trace \
                     the evolution of any file or specific lines through time. Navigate through commits \
                     to see how code has changed, who changed it, and when.")
-        .subcommand(
+        .subcommand(with_load_args(
             Command::new("lines")
                 .about("📏 Trace the evolution of specific lines in a file")
                 .long_about("Focus on a specific range of lines and see how they evolved across commits. \
@@ -70,11 +74,21 @@ fn main() {
                     Arg::new("file_range")
                         .help("File and line range to analyze")
                         .long_help("Specify the file and line range in the format: path/to/file.ext:start-end\n\
+                                   Functions and patterns are found in the newest version, as git log -L does.\n\
                                    Examples:\n  \
-                                   src/main.rs:10-20    (lines 10 through 20)\n  \
-                                   lib.py:50            (just line 50)\n  \
-                                   config.json:1-10     (first 10 lines)")
+                                   src/main.rs:10-20          (lines 10 through 20)\n  \
+                                   lib.py:50                  (just line 50)\n  \
+                                   config.json:1-10           (first 10 lines)\n  \
+                                   src/auth.rs:validate_token (the function whose header matches)\n  \
+                                   app.py:/^def load/,/^def / (from one pattern to the next)\n  \
+                                   app.py:/^def load/,+20     (20 lines from a pattern)\n\
+                                   Several ranges of the same file can be given, separated by commas or as\n\
+                                   separate arguments; each is shown as its own block:\n  \
+                                   src/main.rs:10-20,200-240\n  \
+                                   src/auth.rs:validate_token src/auth.rs:login")
                         .required(true)
+                        .num_args(1..)
+                        .action(clap::ArgAction::Append)
                         .index(1)
                 )
                 .arg(
@@ -86,8 +100,8 @@ fn main() {
                         .short('r')
                         .action(clap::ArgAction::SetTrue)
                 )
-        )
-        .subcommand(
+        ))
+        .subcommand(with_load_args(
             Command::new("file")
                 .about("📄 Show the evolution of an entire file")
                 .long_about("View the complete history of a file from its first commit to the present. \
@@ -112,28 +126,117 @@ fn main() {
                         .short('r')
                         .action(clap::ArgAction::SetTrue)
                 )
+                .arg(
+                    Arg::new("full_blame")
+                        .help("Run a full git blame for every version")
+                        .long_help("By default each version's blame is derived from its predecessor plus the commit's diff. \
+                                   Only the oldest version, one in every 50, merges, and versions whose parent had a \
+                                   different file than their predecessor (as when it came from another branch) are blamed \
+                                   in full. Use --full-blame to blame every version from scratch, e.g. to see lines that \
+                                   were moved from elsewhere.")
+                        .long("full-blame")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        ))
+        .subcommand(with_load_args(
+            Command::new("search")
+                .about("🔎 Find the commits where a string appeared or vanished in a file")
+                .long_about("List the commits that changed how many times a string occurs in a file, as git log -S \
+                           does, following the file through renames. The viewer then steps through just those \
+                           versions, starting at the first with the string highlighted.")
+                .arg(
+                    Arg::new("file_path")
+                        .help("Path to the file to search the history of")
+                        .required(true)
+                        .index(1)
+                )
+                .arg(
+                    Arg::new("pattern")
+                        .help("String to look for, matched exactly")
+                        .long_help("The string to look for, case included. With --regex it is a regular expression, \
+                                   as with git log -S --pickaxe-regex.\n\
+                                   Examples:\n  \
+                                   garch search src/auth.rs validate_token\n  \
+                                   garch search app.py 'def load\\w*' --regex")
+                        .required(true)
+                        .index(2)
+                )
+                .arg(
+                    Arg::new("regex")
+                        .help("Treat the pattern as a regular expression")
+                        .long("regex")
+                        .short('E')
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("reverse")
+                        .help("Show newest commits first instead of oldest first")
+                        .long_help("By default, garch shows the oldest commits first (left) and newest last (right). \
+                                   Use --reverse to start with the newest commits and navigate backwards in time.")
+                        .long("reverse")
+                        .short('r')
+                        .action(clap::ArgAction::SetTrue)
+                )
+        ))
+        .subcommand(
+            Command::new("cache")
+                .about("🗄  Manage the on-disk blame cache")
+                .long_about("Blame results are stored in .git/garch (or $XDG_CACHE_HOME/garch when set) so \
+                           reopening a file doesn't run git blame again. Entries never go stale, since a commit \
+                           hash fixes a file's whole history.")
+                .subcommand_required(true)
+                .subcommand(Command::new("stats").about("Show where the cache lives and how big it is"))
+                .subcommand(Command::new("clear").about("Delete every cached blame"))
         )
         .after_help("INTERACTIVE NAVIGATION:\n  \
                      ← → : Navigate between commits (older/newer)\n  \
-                     ↑ ↓ : Scroll up/down within the current view\n  \
-                     Mouse: Scroll with mouse wheel\n  \
+                     ↑ ↓ : Move the cursor up/down, scrolling the current view\n  \
+                     / ? : Search forwards/backwards as you type\n  \
+                     n N : Move to the next/previous match\n  \
+                     p P : Move to the next/previous version that added or removed the search\n  \
+                     , < : Reblame at the parent of the line's commit / go back\n  \
+                     :   : Jump to a version by number, hash, date, tag or branch\n  \
+                     [ ] : Jump to the previous/next block of changed lines\n  \
+                     Tab : Switch between blame and the commit's diff\n  \
+                     m   : Mark the current version as the base for comparisons\n  \
+                     c   : Compare the marked (or previous) version side by side\n  \
+                     i   : Show or hide the commit details\n  \
+                     d   : Switch date format (short/relative/iso/full)\n  \
+                     z   : Switch timezone (author/local/UTC)\n  \
+                     Mouse: Scroll with mouse wheel; click or drag the timeline to jump\n  \
                      q   : Quit\n\n\
                      EXAMPLES:\n  \
                      garch lines src/main.rs:100-200    # Trace lines 100-200\n  \
+                     garch lines src/auth.rs:validate_token  # Trace a function\n  \
                      garch file README.md               # View entire file history\n  \
-                     garch lines lib.py:50 --reverse    # Start from newest commits")
+                     garch search src/db.rs retry_count # When retry_count came and went\n  \
+                     garch lines lib.py:50 --reverse    # Start from newest commits\n  \
+                     garch cache stats                  # Show blame cache size")
         .get_matches();
 
     match matches.subcommand() {
         Some(("lines", sub_matches)) => {
-            let file_range = sub_matches.get_one::<String>("file_range").unwrap();
+            let file_ranges: Vec<&String> = sub_matches.get_many::<String>("file_range").unwrap().collect();
             let reverse = sub_matches.get_flag("reverse");
-            handle_lines_command(file_range, reverse);
+            let (zone, date_format) = display_dates(sub_matches);
+            handle_lines_command(&file_ranges, reverse, zone, date_format, &load_options(sub_matches));
         }
         Some(("file", sub_matches)) => {
             let file_path = sub_matches.get_one::<String>("file_path").unwrap();
             let reverse = sub_matches.get_flag("reverse");
-            handle_file_command(file_path, reverse);
+            let (zone, date_format) = display_dates(sub_matches);
+            handle_file_command(file_path, reverse, zone, date_format, &load_options(sub_matches));
+        }
+        Some(("search", sub_matches)) => {
+            let file_path = sub_matches.get_one::<String>("file_path").unwrap();
+            let pattern = sub_matches.get_one::<String>("pattern").unwrap();
+            let reverse = sub_matches.get_flag("reverse");
+            let (zone, date_format) = display_dates(sub_matches);
+            let regex = sub_matches.get_flag("regex");
+            handle_search_command(file_path, pattern, regex, reverse, zone, date_format, &load_options(sub_matches));
+        }
+        Some(("cache", sub_matches)) => {
+            handle_cache_command(sub_matches.subcommand_name().unwrap_or("stats"));
         }
         _ => {
             println!("🔍 Git Archaeology (garch) - Explore code evolution through time\n");
@@ -141,52 +244,107 @@ fn main() {
             println!("  garch <SUBCOMMAND> [OPTIONS]\n");
             println!("COMMANDS:");
             println!("  lines <file:range>  Trace specific lines (e.g., src/main.rs:10-20)");
-            println!("  file <file>         View entire file history\n");
+            println!("  file <file>         View entire file history");
+            println!("  search <file> <str> Find where a string appeared or vanished");
+            println!("  cache stats|clear   Inspect or empty the blame cache\n");
             println!("OPTIONS:");
-            println!("  -r, --reverse       Start with newest commits first\n");
+            println!("  -r, --reverse       Start with newest commits first");
+            println!("      --plain         Show contents only, skipping blame");
+            println!("      --timezone <Z>  Show dates in author, local or utc time");
+            println!("      --date <FORMAT> Write dates as short, relative, iso, full or format:<pattern>");
+            println!("  -j, --jobs <N>      Load up to N versions in parallel\n");
             println!("EXAMPLES:");
             println!("  garch lines src/main.rs:100-200    # Trace lines 100-200");
+            println!("  garch lines src/auth.rs:validate_token  # Trace a function");
             println!("  garch file README.md               # View file history");
+            println!("  garch search src/db.rs retry_count # When retry_count came and went");
             println!("  garch lines lib.py:50 --reverse    # Start from newest\n");
             println!("For detailed help: garch --help");
         }
     }
 }
 
-fn handle_lines_command(file_range: &str, reverse: bool) {
-    let (file_path, start_line, end_line) = parse_file_range(file_range);
-    
-    match get_line_history(&file_path, start_line, end_line) {
+/// The in-process backend when built with the `gix` feature, falling back to the `git` binary.
+fn open_backend() -> Arc<dyn GitBackend> {
+    #[cfg(feature = "gix")]
+    if let Ok(backend) = garch::backend::GixBackend::discover() {
+        return Arc::new(backend);
+    }
+    Arc::new(ProcessBackend::new())
+}
+
+fn load_options(sub_matches: &clap::ArgMatches) -> LoadOptions {
+    LoadOptions {
+        blame: !sub_matches.get_flag("plain"),
+        incremental: !sub_matches.try_get_one::<bool>("full_blame").ok().flatten().copied().unwrap_or(false),
+        jobs: sub_matches.get_one::<usize>("jobs").copied().unwrap_or_else(default_jobs),
+        // Without a cache every version is simply blamed again
+        cache: BlameCache::open().ok(),
+        ..LoadOptions::default()
+    }
+}
+
+/// The timezone and format dates are shown in.
+fn display_dates(sub_matches: &clap::ArgMatches) -> (DisplayZone, DateFormat) {
+    let zone = sub_matches.get_one::<String>("timezone")
+        .and_then(|name| DisplayZone::parse(name))
+        .unwrap_or_default();
+    let format = sub_matches.get_one::<DateFormat>("date").cloned().unwrap_or_default();
+    (zone, format)
+}
+
+fn handle_lines_command(file_ranges: &[&String], reverse: bool, zone: DisplayZone, date_format: DateFormat, options: &LoadOptions) {
+    // Every argument has to name the same file; their ranges are followed together
+    let mut file_path = String::new();
+    let mut targets = Vec::new();
+    for file_range in file_ranges {
+        match parse_file_range(file_range) {
+            Ok((path, more)) if file_path.is_empty() || path == file_path => {
+                file_path = path;
+                targets.extend(more);
+            }
+            Ok((path, _)) => {
+                eprintln!("Error: all ranges must be in the same file, but got {} and {}", file_path, path);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let backend = open_backend();
+
+    // Function names and patterns are found in the newest version; older versions follow
+    // wherever those lines moved
+    let mut ranges = Vec::new();
+    for target in &targets {
+        match target.resolve(&*backend, &file_path) {
+            Ok(range) => ranges.push(range),
+            Err(e) => {
+                eprintln!("Error finding lines in {}: {}", file_path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match get_line_history_for_ranges(&*backend, &file_path, &ranges) {
         Ok(commits) => {
             if commits.is_empty() {
-                println!("No history found for {}:{}-{}", file_path, start_line, end_line);
+                let ranges: Vec<String> = ranges.iter().map(|(start, end)| format!("{}-{}", start, end)).collect();
+                println!("No history found for {}:{}", file_path, ranges.join(","));
                 return;
             }
             
-            // Build file versions that only include commits where the specified lines exist
-            match get_file_versions_for_lines(&file_path, start_line, end_line) {
-                Ok(mut versions) => {
-                    if versions.is_empty() {
-                        println!("No versions found where lines {}-{} exist in {}", start_line, end_line, file_path);
-                        return;
-                    }
-                    
-                    // By default, show oldest first (reverse the git log order)
-                    // If reverse flag is set, keep newest first
-                    if !reverse {
-                        versions.reverse();
-                    }
-                    
-                    if let Err(e) = run_interactive_viewer(&file_path, versions, start_line, end_line) {
-                        eprintln!("Error running interactive viewer: {}", e);
-                        std::process::exit(1);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error getting file versions: {}", e);
-                    std::process::exit(1);
-                }
+            // Versions are blamed as they are viewed; commits from git log -L aren't
+            // consecutive, so each one gets a blame of its own
+            let history = LazyVersions::new(backend, &file_path, commits, false, options);
+            let viewer_options = ViewerOptions { ranges, reverse, plain: !options.blame, zone, date_format, search: None, regex: false };
+            if let Err(e) = run_interactive_viewer(&history, &viewer_options) {
+                eprintln!("Error running interactive viewer: {}", e);
+                std::process::exit(1);
             }
+            report_skipped(&history);
         }
         Err(e) => {
             eprintln!("Error getting line history: {}", e);
@@ -195,23 +353,22 @@ fn handle_lines_command(file_range: &str, reverse: bool) {
     }
 }
 
-fn handle_file_command(file_path: &str, reverse: bool) {
+fn handle_file_command(file_path: &str, reverse: bool, zone: DisplayZone, date_format: DateFormat, options: &LoadOptions) {
     println!("Loading file history for {}...", file_path);
+    let backend = open_backend();
     
-    match get_file_versions(file_path) {
-        Ok(mut versions) => {
-            if versions.is_empty() {
+    match get_file_history(&*backend, file_path) {
+        Ok(commits) => {
+            if commits.is_empty() {
                 println!("No git history found for {}", file_path);
                 return;
             }
             
-            // Apply reverse ordering if requested
-            if !reverse {
-                versions.reverse(); // By default, show oldest first
-            }
-            
-            match run_interactive_viewer(file_path, versions, 1, usize::MAX) {
-                Ok(_) => {},
+            // Only the commit list is read up front; versions are blamed as they are viewed
+            let history = LazyVersions::new(backend, file_path, commits, true, options);
+            let viewer_options = ViewerOptions { ranges: Vec::new(), reverse, plain: !options.blame, zone, date_format, search: None, regex: false };
+            match run_interactive_viewer(&history, &viewer_options) {
+                Ok(_) => report_skipped(&history),
                 Err(e) => eprintln!("Error running interactive viewer: {}", e),
             }
         }
@@ -221,694 +378,86 @@ fn handle_file_command(file_path: &str, reverse: bool) {
     }
 }
 
-fn parse_file_range(file_range: &str) -> (String, usize, usize) {
-    if let Some(colon_pos) = file_range.rfind(':') {
-        let file_path = file_range[..colon_pos].to_string();
-        let range_part = &file_range[colon_pos + 1..];
-        if let Some(dash_pos) = range_part.find('-') {
-            let start_line: usize = range_part[..dash_pos].parse().unwrap_or(1);
-            let end_line: usize = range_part[dash_pos + 1..].parse().unwrap_or(start_line);
-            (file_path, start_line, end_line)
-        } else {
-            let line_num: usize = range_part.parse().unwrap_or(1);
-            (file_path, line_num, line_num)
-        }
-    } else {
-        (file_range.to_string(), 1, usize::MAX)
-    }
-}
-
-fn format_timestamp(timestamp: i64) -> String {
-    // Simple timestamp formatting - in a real app you'd use chrono
-    use std::time::{UNIX_EPOCH, Duration};
-    
-    if let Some(datetime) = UNIX_EPOCH.checked_add(Duration::from_secs(timestamp as u64)) {
-        let days = datetime.duration_since(UNIX_EPOCH).unwrap().as_secs() / 86400;
-        
-        // Very rough date calculation - just for demo
-        let year = 1970 + (days / 365);
-        let day_of_year = days % 365;
-        let month = (day_of_year / 30) + 1;
-        let day = (day_of_year % 30) + 1;
-        
-        return format!("{:04}-{:02}-{:02}", year, month.min(12), day.min(31));
-    }
-    
-    "unknown".to_string()
-}
-
-fn find_closest_line_in_filtered(filtered_lines: &[&BlameLine], target_line: usize) -> Option<usize> {
-    if filtered_lines.is_empty() {
-        return None;
-    }
-    
-    // Try to find the exact line first
-    if let Some(pos) = filtered_lines.iter().position(|line| line.line_number == target_line) {
-        return Some(pos);
-    }
-    
-    // Find the closest line by minimum distance
-    let mut closest_pos = 0;
-    let mut min_distance = usize::MAX;
-    
-    for (pos, line) in filtered_lines.iter().enumerate() {
-        let distance = if line.line_number > target_line {
-            line.line_number - target_line
-        } else {
-            target_line - line.line_number
-        };
-        
-        if distance < min_distance {
-            min_distance = distance;
-            closest_pos = pos;
-        }
-    }
-    
-    Some(closest_pos)
-}
-
-fn get_current_target_line(filtered_lines: &[&BlameLine], scroll_offset: usize, content_height: usize) -> usize {
-    // Get the line number of the first visible line, or middle if multiple lines visible
-    let visible_start = scroll_offset;
-    let visible_end = (scroll_offset + content_height / 2).min(filtered_lines.len());
-    
-    if let Some(line) = filtered_lines.get(visible_start.max(visible_end.saturating_sub(1))) {
-        line.line_number
-    } else if let Some(line) = filtered_lines.first() {
-        line.line_number
-    } else {
-        1 // fallback
-    }
-}
-
-fn get_line_history(file_path: &str, start_line: usize, end_line: usize) -> Result<Vec<CommitInfo>, String> {
-    let range = format!("{},{}", start_line, end_line);
-    let output = ProcessCommand::new("git")
-        .args([
-            "log",
-            // Note: --follow is not compatible with -L, git will track renames automatically for -L
-            "-L", &format!("{}:{}", range, file_path),
-            "--pretty=format:%H|%ad|%an|%s",
-            "--date=short",
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run git command: {}", e))?;
-
-    if !output.status.success() {
-        return Err(format!("Git command failed: {}", 
-            std::str::from_utf8(&output.stderr).unwrap_or("unknown error")));
-    }
-
-    let output_str = std::str::from_utf8(&output.stdout)
-        .map_err(|e| format!("Invalid UTF-8 in git output: {}", e))?;
+fn handle_search_command(file_path: &str, pattern: &str, regex: bool, reverse: bool, zone: DisplayZone, date_format: DateFormat, options: &LoadOptions) {
+    let backend = open_backend();
 
-    let mut commits = Vec::new();
-    for line in output_str.lines() {
-        if line.contains('|') {
-            if let Some(commit) = parse_commit_line(line) {
-                commits.push(commit);
+    match get_pickaxe_history(&*backend, file_path, pattern, regex) {
+        Ok(commits) => {
+            if commits.is_empty() {
+                println!("No commit changed how often '{}' occurs in {}", pattern, file_path);
+                return;
             }
-        }
-    }
-
-    Ok(commits)
-}
-
-fn get_file_history(file_path: &str) -> Result<Vec<CommitInfo>, String> {
-    let output = ProcessCommand::new("git")
-        .args([
-            "log",
-            "--follow",
-            "--pretty=format:%H|%ad|%an|%s",
-            "--date=short",
-            "--",
-            file_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run git command: {}", e))?;
-
-    if !output.status.success() {
-        return Err(format!("Git command failed: {}", 
-            std::str::from_utf8(&output.stderr).unwrap_or("unknown error")));
-    }
-
-    let output_str = std::str::from_utf8(&output.stdout)
-        .map_err(|e| format!("Invalid UTF-8 in git output: {}", e))?;
-
-    let commits: Vec<CommitInfo> = output_str
-        .lines()
-        .filter_map(parse_commit_line)
-        .collect();
-
-    Ok(commits)
-}
-
-fn parse_commit_line(line: &str) -> Option<CommitInfo> {
-    let parts: Vec<&str> = line.split('|').collect();
-    if parts.len() >= 4 {
-        Some(CommitInfo {
-            hash: parts[0].to_string(),
-            date: parts[1].to_string(),
-            author: parts[2].to_string(),
-            message: parts[3].to_string(),
-        })
-    } else {
-        None
-    }
-}
-
-fn get_author_color(author: &str) -> Color {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut hasher = DefaultHasher::new();
-    author.hash(&mut hasher);
-    let hash = hasher.finish();
-    
-    let colors = [
-        Color::Red,
-        Color::DarkCyan,
-        Color::DarkGreen,
-        Color::DarkYellow,
-        Color::DarkBlue,
-        Color::DarkMagenta,
-        Color::DarkRed,
-    ];
-    colors[hash as usize % colors.len()]
-}
-
-fn abbreviate_author(author: &str) -> String {
-    let parts: Vec<&str> = author.split_whitespace().collect();
-    if parts.len() >= 2 {
-        format!("{} {}.", parts[0], parts[1].chars().next().unwrap_or('?'))
-    } else {
-        author.to_string()
-    }
-}
 
-fn get_file_versions(file_path: &str) -> Result<Vec<FileVersion>, String> {
-    let commits = get_file_history(file_path)?;
-    let mut versions = Vec::new();
-    
-    for commit in commits {
-        match get_blame_for_commit(&commit.hash, file_path) {
-            Ok(blame_lines) => {
-                versions.push(FileVersion {
-                    commit_hash: commit.hash.clone(),
-                    commit_date: commit.date,
-                    commit_message: commit.message,
-                    blame_lines,
-                });
+            // The list stays on the terminal once the viewer closes
+            println!("'{}' was added or removed in {} commit(s) to {}:", pattern, commits.len(), file_path);
+            for commit in &commits {
+                let short_hash: String = commit.hash.chars().take(8).collect();
+                println!("  {} {} {:<20} {}", short_hash, date_format.format(&commit.time, zone), commit.author, commit.message);
             }
-            Err(_) => continue, // Skip commits where we can't get blame
-        }
-    }
-    
-    Ok(versions)
-}
 
-fn get_file_versions_for_lines(file_path: &str, start_line: usize, end_line: usize) -> Result<Vec<FileVersion>, String> {
-    // Get commits that touched the specific line range using git log -L
-    let commits = get_line_history(file_path, start_line, end_line)?;
-    let mut versions = Vec::new();
-    
-    for commit in commits {
-        match get_blame_for_commit(&commit.hash, file_path) {
-            Ok(blame_lines) => {
-                // Check if any of the specified lines exist in this commit
-                let has_target_lines = blame_lines.iter()
-                    .any(|line| line.line_number >= start_line && line.line_number <= end_line);
-                
-                if has_target_lines {
-                    versions.push(FileVersion {
-                        commit_hash: commit.hash.clone(),
-                        commit_date: commit.date,
-                        commit_message: commit.message,
-                        blame_lines,
-                    });
-                }
+            // Like git log -L, these commits aren't consecutive, so each is blamed on its own
+            let history = LazyVersions::new(backend, file_path, commits, false, options);
+            let viewer_options = ViewerOptions {
+                ranges: Vec::new(),
+                reverse,
+                plain: !options.blame,
+                zone,
+                date_format,
+                search: Some(pattern.to_string()),
+                regex,
+            };
+            match run_interactive_viewer(&history, &viewer_options) {
+                Ok(_) => report_skipped(&history),
+                Err(e) => eprintln!("Error running interactive viewer: {}", e),
             }
-            Err(_) => continue, // Skip commits where we can't get blame
         }
-    }
-    
-    Ok(versions)
-}
-
-fn get_blame_for_commit(commit_hash: &str, file_path: &str) -> Result<Vec<BlameLine>, String> {
-    let output = ProcessCommand::new("git")
-        .args([
-            "blame",
-            "--line-porcelain",
-            commit_hash,
-            "--",
-            file_path,
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run git blame: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("unknown error");
-        // Check if this is because the file doesn't exist in this commit
-        if stderr.contains("no such path") || stderr.contains("does not exist") {
-            return Err(format!("File does not exist in commit {}", commit_hash));
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
-        return Err(format!("Git blame failed: {}", stderr));
     }
-
-    let output_str = std::str::from_utf8(&output.stdout)
-        .map_err(|e| format!("Invalid UTF-8 in git blame output: {}", e))?;
-
-    Ok(parse_blame_output_with_highlighting(output_str, file_path))
 }
 
-fn parse_blame_output_with_highlighting(blame_text: &str, file_path: &str) -> Vec<BlameLine> {
-    // Load syntax and theme sets once for the entire file
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    
-    // Try to determine syntax by file extension
-    let syntax = ps.find_syntax_for_file(file_path)
-        .unwrap_or(None)
-        .unwrap_or_else(|| ps.find_syntax_plain_text());
-    
-    // Use a dark theme
-    let theme = &ts.themes["base16-ocean.dark"];
-    let mut h = HighlightLines::new(syntax, theme);
-    
-    let mut blame_lines = Vec::new();
-    let lines: Vec<&str> = blame_text.lines().collect();
-    let mut i = 0;
-    
-    while i < lines.len() {
-        if let Some(line) = lines.get(i) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 3 && parts[0].len() >= 7 {
-                let commit_hash = parts[0].to_string();
-                let line_number: usize = parts[2].parse().unwrap_or(0);
-                
-                // Look for author, date, and summary in the following lines
-                let mut author = String::new();
-                let mut date = String::new();
-                let mut commit_message = String::new();
-                let mut content = String::new();
-                
-                i += 1;
-                while i < lines.len() {
-                    if let Some(info_line) = lines.get(i) {
-                        if info_line.starts_with("author ") {
-                            author = info_line[7..].to_string();
-                        } else if info_line.starts_with("author-time ") {
-                            // Convert timestamp to readable date
-                            if let Ok(timestamp) = info_line[12..].parse::<i64>() {
-                                date = format_timestamp(timestamp);
-                            }
-                        } else if info_line.starts_with("summary ") {
-                            commit_message = info_line[8..].to_string();
-                        } else if info_line.starts_with('\t') {
-                            content = info_line[1..].to_string(); // Remove leading tab
-                            i += 1;
-                            break;
-                        }
-                    }
-                    i += 1;
-                }
-                
-                // Apply syntax highlighting to this line
-                let highlighted_content = if content.len() > 200 || content.trim().is_empty() {
-                    // For very long lines or empty lines, skip highlighting for performance/stability
-                    content.clone()
-                } else {
-                    // Try to apply syntax highlighting, fallback to plain text on any error
-                    match h.highlight_line(&content, &ps) {
-                        Ok(ranges) => {
-                            let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
-                            // Ensure the highlighted content ends with a reset sequence
-                            if escaped.contains('\x1b') && !escaped.ends_with("\x1b[0m") {
-                                format!("{}\x1b[0m", escaped)
-                            } else {
-                                escaped
-                            }
-                        }
-                        Err(_) => content.clone(), // Fallback to plain text
-                    }
-                };
-                
-                blame_lines.push(BlameLine {
-                    line_number,
-                    author: abbreviate_author(&author),
-                    date,
-                    commit_hash: commit_hash[..7].to_string(),
-                    commit_message,
-                    content,
-                    highlighted_content,
-                });
-            } else {
-                i += 1;
-            }
-        } else {
-            break;
-        }
+/// List the versions that couldn't be loaded, once the viewer has given the terminal back.
+fn report_skipped(history: &LazyVersions) {
+    let skipped = history.skipped();
+    if skipped.is_empty() {
+        return;
+    }
+    eprintln!("Skipped {} version(s) of {} that couldn't be loaded:", skipped.len(), history.file_path());
+    for version in skipped {
+        let short_hash: String = version.commit.hash.chars().take(8).collect();
+        eprintln!("  {} {}: {}", short_hash, version.commit.message, version.error);
     }
-    
-    blame_lines
 }
 
-fn run_interactive_viewer(file_path: &str, versions: Vec<FileVersion>, _start_line: usize, _end_line: usize) -> Result<(), Box<dyn std::error::Error>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    
-    let mut current_version = 0;
-    let mut scroll_offset = 0;
-    // Temporarily disable target line tracking to fix basic scrolling
-    // let mut target_line: Option<usize> = None;
-
-    loop {
-        let (terminal_width, terminal_height) = crossterm::terminal::size()?;
-        let content_height = terminal_height as usize - 4; // Reserve space for 3-line header + 1-line footer
-
-        // Calculate filtered lines first so they're available for both display and navigation
-        let version = &versions[current_version];
-        
-        // Filter lines based on the specified range (only for lines command, not file command)
-        let filtered_lines: Vec<&BlameLine> = if _start_line == 1 && _end_line == usize::MAX {
-            // File command - show all lines
-            version.blame_lines.iter().collect()
-        } else {
-            // Lines command - filter to the specified range
-            version.blame_lines.iter()
-                .filter(|line| line.line_number >= _start_line && line.line_number <= _end_line)
-                .collect()
-        };
-
-        // Smart bounds checking - try to preserve the viewing position
-        let max_scroll = if filtered_lines.len() <= content_height {
-            0
-        } else {
-            filtered_lines.len() - content_height
-        };
-        
-        // Ensure scroll_offset is within valid bounds - this is critical!
-        scroll_offset = scroll_offset.min(max_scroll);
-        
-        // Double-check: if we have any lines at all, scroll_offset should never exceed the array bounds
-        if !filtered_lines.is_empty() && scroll_offset >= filtered_lines.len() {
-            scroll_offset = 0; // Reset to top if we're somehow out of bounds
-        }
-
-        // Move to top and clear the screen - but do it all at once
-        execute!(stdout, crossterm::cursor::MoveTo(0, 0))?;
-        execute!(stdout, crossterm::terminal::Clear(crossterm::terminal::ClearType::All))?;
-
-        // Header with colors
-        execute!(stdout, SetForegroundColor(Color::White), SetBackgroundColor(Color::DarkBlue))?;
-
-        // Main header line with file, version number, and date
-        let header_text = format!("{} | {} of {} | {}",
-            file_path,
-            current_version + 1,
-            versions.len(),
-            version.commit_date
-        );
-        print!("{}", header_text);
-
-        // Pad to full width
-        if header_text.len() < terminal_width as usize {
-            print!("{}", " ".repeat(terminal_width as usize - header_text.len()));
-        }
-        execute!(stdout, ResetColor)?;
-        println!("\r");
-
-        // Commit details line
-        execute!(stdout, SetForegroundColor(Color::Yellow))?;
-        let commit_short = if version.commit_hash.len() > 8 {
-            &version.commit_hash[..8]
-        } else {
-            &version.commit_hash
-        };
-        let commit_line = format!("{} | {}", commit_short, version.commit_message);
-
-        // Truncate commit message if too long
-        let max_commit_line_len = terminal_width as usize;
-        let display_commit_line = if commit_line.len() > max_commit_line_len {
-            format!("{}...", &commit_line[..max_commit_line_len.saturating_sub(3)])
-        } else {
-            commit_line
-        };
-
-        print!("{}", display_commit_line);
-        if display_commit_line.len() < terminal_width as usize {
-            print!("{}", " ".repeat(terminal_width as usize - display_commit_line.len()));
+fn handle_cache_command(action: &str) {
+    let cache = match BlameCache::open() {
+        Ok(cache) => cache,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
-        execute!(stdout, ResetColor)?;
-        println!("\r");
-
-        // Separator line
-        execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
-        println!("{}\r", "─".repeat(terminal_width as usize));
-        execute!(stdout, ResetColor)?;
-
-        // Ensure we're in a clean state before drawing content
-        execute!(stdout, ResetColor, SetBackgroundColor(Color::Reset))?;
+    };
 
-        // Content with colors (filtered lines already calculated above)
-        
-        let display_end = (scroll_offset + content_height).min(filtered_lines.len());
-        let mut last_author = String::new();
-        let content_width = terminal_width as usize - 20; // Reserve space for line numbers and margins
-        let mut lines_displayed = 0; // Track actual screen lines used
-        
-        for i in scroll_offset..display_end {
-            if let Some(line) = filtered_lines.get(i) {
-                // Check if we need to show author info (first line or author changed)
-                let show_author = last_author != line.author;
-                
-                // Stop if we would exceed screen space (accounting for author headers)
-                let lines_needed = if show_author { 2 } else { 1 }; // Author header + content line
-                if lines_displayed + lines_needed > content_height {
-                    break;
-                }
-                
-                if show_author {
-                    last_author = line.author.clone();
-                    
-                    // Author header line with color
-                    let author_color = get_author_color(&line.author);
-                    execute!(stdout, SetForegroundColor(author_color))?;
-                    print!("┌─ {} ", line.author);
-                    execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
-                    print!("({}) ", line.date);
-                    execute!(stdout, SetForegroundColor(Color::Yellow))?;
-                    print!("[{}] ", line.commit_hash);
-                    execute!(stdout, SetForegroundColor(Color::White))?;
-                    print!("{}", line.commit_message);
-                    execute!(stdout, ResetColor)?;
-                    println!("\r");
-                    lines_displayed += 1;
-                }
-                
-                // Line number with proper spacing
-                execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
-                print!("│ {:3} │ ", line.line_number);
-                execute!(stdout, ResetColor)?;
-                
-                // Content with line wrapping - use pre-rendered highlighted content
-                let content = &line.content;
-                let highlighted_content = &line.highlighted_content;
-                
-                if content.len() <= content_width {
-                    // Single line - no wrapping needed
-                    println!("{}\r", highlighted_content);
-                    lines_displayed += 1;
-                } else {
-                    // For long lines, just truncate to avoid wrapping complexity with ANSI codes
-                    if content.len() > content_width {
-                        // Use plain content for truncation to avoid cutting ANSI escape sequences
-                        let truncated = format!("{}...", &content[..content_width.saturating_sub(3)]);
-                        println!("{}\r", truncated);
-                    } else {
-                        println!("{}\r", highlighted_content);
-                    }
-                    lines_displayed += 1;
-                }
-            }
-        }
-        // Footer with colors
-        execute!(stdout, crossterm::cursor::MoveTo(0, terminal_height - 1))?;
-        execute!(stdout, SetForegroundColor(Color::White), SetBackgroundColor(Color::DarkGrey))?;
-        let footer_text = "← Older    Newer → │ ↑ ↓ : Scroll │ Mouse: Scroll │ q : Quit";
-        print!("{}", footer_text);
-        // Pad footer to full width
-        if footer_text.len() < terminal_width as usize {
-            print!("{}", " ".repeat(terminal_width as usize - footer_text.len()));
-        }
-        execute!(stdout, ResetColor)?;
-        print!("\r");
-        stdout.flush()?;
-        // Handle input including mouse
-        match event::read()? {
-            Event::Key(key) => {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') => break,
-                        KeyCode::Left => {
-                            if current_version > 0 {
-                                current_version -= 1;
-                                // Don't reset scroll immediately - let the bounds checking handle it
-                            }
-                        }
-                        KeyCode::Right => {
-                            if current_version < versions.len() - 1 {
-                                current_version += 1;
-                                // Don't reset scroll immediately - let the bounds checking handle it
-                            }
-                        }
-                        KeyCode::Up => {
-                            if scroll_offset > 0 {
-                                scroll_offset -= 1;
-                            }
-                        }
-                        KeyCode::Down => {
-                            let max_scroll = if filtered_lines.len() > content_height {
-                                filtered_lines.len() - content_height
-                            } else {
-                                0
-                            };
-                            if scroll_offset < max_scroll {
-                                scroll_offset += 1;
-                            }
-                        }
-                        KeyCode::PageUp => {
-                            scroll_offset = scroll_offset.saturating_sub(content_height / 2);
-                        }
-                        KeyCode::PageDown => {
-                            let max_scroll = if filtered_lines.len() > content_height {
-                                filtered_lines.len() - content_height
-                            } else {
-                                0
-                            };
-                            scroll_offset = (scroll_offset + content_height / 2).min(max_scroll);
-                        }
-                        KeyCode::Home => {
-                            scroll_offset = 0;
-                        }
-                        KeyCode::End => {
-                            let max_scroll = if filtered_lines.len() > content_height {
-                                filtered_lines.len() - content_height
-                            } else {
-                                0
-                            };
-                            scroll_offset = max_scroll;
-                        }
-                        _ => {}
-                    }
-                }
+    match action {
+        "clear" => match cache.clear() {
+            Ok(()) => println!("Cleared blame cache in {}", cache.dir().display()),
+            Err(e) => {
+                eprintln!("Error clearing cache: {}", e);
+                std::process::exit(1);
             }
-            Event::Mouse(mouse) => {
-                match mouse.kind {
-                    MouseEventKind::ScrollUp => {
-                        scroll_offset = scroll_offset.saturating_sub(3); // Scroll 3 lines at a time
-                    }
-                    MouseEventKind::ScrollDown => {
-                        let max_scroll = if filtered_lines.len() > content_height {
-                            filtered_lines.len() - content_height
-                        } else {
-                            0
-                        };
-                        scroll_offset = (scroll_offset + 3).min(max_scroll);
-                    }
-                    _ => {}
-                }
+        },
+        _ => match cache.stats() {
+            Ok(stats) => {
+                println!("Cache directory: {}", cache.dir().display());
+                println!("Entries:         {} ({} commits)", stats.entries, stats.commits);
+                println!("Size:            {:.1} KiB", stats.bytes as f64 / 1024.0);
             }
-            _ => {}
-        }
-    }
-    // Cleanup
-    disable_raw_mode()?;
-    execute!(stdout, LeaveAlternateScreen)?;
-    Ok(())
-}
-
-fn get_commit_changes(commit_hash: &str, file_path: &str, start_line: usize, end_line: usize) -> Result<Vec<LineChange>, String> {
-    let range = format!("{},{}", start_line, end_line);
-    let output = ProcessCommand::new("git")
-        .args([
-            "show",
-            commit_hash,
-            "-L", &format!("{}:{}", range, file_path),
-        ])
-        .output()
-        .map_err(|e| format!("Failed to run git show: {}", e))?;
-
-    if !output.status.success() {
-        return Ok(vec![]); // Return empty if git show fails
-    }
-
-    let output_str = std::str::from_utf8(&output.stdout)
-        .map_err(|e| format!("Invalid UTF-8 in git show output: {}", e))?;
-
-    Ok(parse_diff_output(output_str))
-}
-
-fn parse_diff_output(diff_text: &str) -> Vec<LineChange> {
-    let mut changes = Vec::new();
-    let mut in_diff = false;
-    let mut line_number = 0;
-
-    for line in diff_text.lines() {
-        // Look for the @@ hunk header to start parsing
-        if line.starts_with("@@") {
-            in_diff = true;
-            // Parse the line number from @@ -old_start,old_count +new_start,new_count @@
-            if let Some(plus_pos) = line.find('+') {
-                if let Some(comma_pos) = line[plus_pos..].find(',') {
-                    let start_str = &line[plus_pos + 1..plus_pos + comma_pos];
-                    line_number = start_str.parse().unwrap_or(1);
-                } else if let Some(space_pos) = line[plus_pos..].find(' ') {
-                    let start_str = &line[plus_pos + 1..plus_pos + space_pos];
-                    line_number = start_str.parse().unwrap_or(1);
-                }
+            Err(e) => {
+                eprintln!("Error reading cache: {}", e);
+                std::process::exit(1);
             }
-            continue;
-        }
-
-        if !in_diff {
-            continue;
-        }
-
-        // Stop at the next commit or end of diff
-        if line.starts_with("commit ") || line.starts_with("diff --git") {
-            break;
-        }
-
-        if line.starts_with('+') && !line.starts_with("+++") {
-            changes.push(LineChange {
-                line_number,
-                change_type: ChangeType::Added,
-                content: line[1..].to_string(), // Remove the + prefix
-            });
-            line_number += 1;
-        } else if line.starts_with('-') && !line.starts_with("---") {
-            changes.push(LineChange {
-                line_number,
-                change_type: ChangeType::Removed,
-                content: line[1..].to_string(), // Remove the - prefix
-            });
-            // Don't increment line_number for removed lines
-        } else if line.starts_with(' ') {
-            // Context line - increment line number but don't show it
-            line_number += 1;
-        }
+        },
     }
-
-    changes
 }
-
-fn display_change(change: &LineChange) {
-    let prefix = match change.change_type { ChangeType::Added => "│  +", ChangeType::Removed => "│  -",
-        ChangeType::Modified => "│  ~",
-    };
-    
-    println!("{} {}", prefix, change.content);
-}
\ No newline at end of file
@@ -0,0 +1,51 @@
+//! A small bounded worker pool for running git operations concurrently.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Default worker count: one per CPU, capped so a large machine doesn't spawn dozens of git processes.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(8)
+}
+
+/// Apply `f` to every item on at most `jobs` threads, returning results in input order.
+pub fn map_ordered<T, R, F>(items: Vec<T>, jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let workers = jobs.max(1).min(items.len());
+    if workers <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+
+    let count = items.len();
+    let queue: Vec<Mutex<Option<T>>> = items.into_iter().map(|item| Mutex::new(Some(item))).collect();
+    let results: Vec<Mutex<Option<R>>> = (0..count).map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= count {
+                    break;
+                }
+                let item = queue[index].lock().unwrap().take();
+                if let Some(item) = item {
+                    let result = f(item);
+                    *results[index].lock().unwrap() = Some(result);
+                }
+            });
+        }
+    });
+
+    results.into_iter()
+        .map(|slot| slot.into_inner().unwrap().expect("every queued item is processed"))
+        .collect()
+}
@@ -0,0 +1,189 @@
+use regex::Regex;
+
+use crate::backend::GitBackend;
+use crate::error::{Error, Result};
+use crate::git::get_file_content;
+
+/// Which lines of a file to follow, in the forms `git log -L` accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineTarget {
+    /// `start-end`, or a single line number.
+    Lines { start: usize, end: usize },
+    /// `funcname`: the first function whose header line matches this regex, up to the
+    /// next function, like `git log -L :funcname:file`.
+    Function(String),
+    /// `/start/,end`: from the first line matching `start` to wherever `end` says.
+    Pattern { start: String, end: PatternEnd },
+}
+
+/// Where a [`LineTarget::Pattern`] range stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternEnd {
+    /// `/regex/`: the next line matching it, inclusive.
+    Pattern(String),
+    /// `+count`: this many lines, counting the first.
+    Count(usize),
+    /// Nothing after the start pattern: the end of the file.
+    EndOfFile,
+}
+
+/// Split `path:targets` into the path and the lines to follow. Targets are written
+/// `start-end`, `line`, `funcname` or `/regex/,/regex/` (also `/regex/,+count` and
+/// `/regex/` on its own, which runs to the end of the file), and several can be given
+/// separated by commas, as in `src/main.rs:10-20,200-240`.
+///
+/// A missing target selects the whole file (`1..=usize::MAX`).
+pub fn parse_file_range(file_range: &str) -> Result<(String, Vec<LineTarget>)> {
+    // Targets can contain colons of their own (`Foo::new`, `/a:b/`), so the path ends at
+    // the first one, apart from a Windows drive letter
+    let drive = file_range.len() > 2
+        && file_range.as_bytes()[0].is_ascii_alphabetic()
+        && file_range[1..].starts_with(":\\");
+    let search_from = if drive { 2 } else { 0 };
+    let Some(colon_pos) = file_range[search_from..].find(':').map(|pos| pos + search_from) else {
+        return Ok((file_range.to_string(), vec![LineTarget::Lines { start: 1, end: usize::MAX }]));
+    };
+    let file_path = file_range[..colon_pos].to_string();
+
+    let mut targets = Vec::new();
+    let mut rest = &file_range[colon_pos + 1..];
+    loop {
+        let (target, after) = if rest.starts_with('/') {
+            parse_pattern(rest)?
+        } else {
+            let (part, after) = rest.find(',').map_or((rest, ""), |comma| (&rest[..comma], &rest[comma..]));
+            (parse_lines(part), after)
+        };
+        targets.push(target);
+        match after.strip_prefix(',') {
+            Some(next) => rest = next,
+            None if after.is_empty() => return Ok((file_path, targets)),
+            None => return Err(Error::InvalidArgument(format!("Invalid line range '{}': expected a comma before '{}'", file_range, after))),
+        }
+    }
+}
+
+/// `start-end`, a single line number, or anything else as a function name.
+fn parse_lines(part: &str) -> LineTarget {
+    if !part.is_empty() && !part.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        return LineTarget::Function(part.to_string());
+    }
+    if let Some(dash_pos) = part.find('-') {
+        let start: usize = part[..dash_pos].parse().unwrap_or(1);
+        let end: usize = part[dash_pos + 1..].parse().unwrap_or(start);
+        LineTarget::Lines { start, end }
+    } else {
+        let line_num: usize = part.parse().unwrap_or(1);
+        LineTarget::Lines { start: line_num, end: line_num }
+    }
+}
+
+/// `/start/`, `/start/,/end/` or `/start/,+count` at the front of `spec`, and what follows it.
+fn parse_pattern(spec: &str) -> Result<(LineTarget, &str)> {
+    let invalid = || Error::InvalidArgument(format!("Invalid line range '{}': expected /regex/,/regex/ or /regex/,+count", spec));
+    let (start, rest) = split_regex(spec).ok_or_else(invalid)?;
+
+    let Some(end) = rest.strip_prefix(',') else {
+        return Ok((LineTarget::Pattern { start, end: PatternEnd::EndOfFile }, rest));
+    };
+    if let Some(count) = end.strip_prefix('+') {
+        let digits = count.find(|c: char| !c.is_ascii_digit()).unwrap_or(count.len());
+        let count: usize = count[..digits].parse().ok().filter(|&count| count > 0).ok_or_else(invalid)?;
+        return Ok((LineTarget::Pattern { start, end: PatternEnd::Count(count) }, &end[1 + digits..]));
+    }
+    match split_regex(end) {
+        Some((pattern, after)) => Ok((LineTarget::Pattern { start, end: PatternEnd::Pattern(pattern) }, after)),
+        // Not an end pattern, so the comma starts the next target
+        None => Ok((LineTarget::Pattern { start, end: PatternEnd::EndOfFile }, rest)),
+    }
+}
+
+/// The regex in a leading `/.../` (where `\/` stands for a slash) and whatever follows it.
+fn split_regex(spec: &str) -> Option<(String, &str)> {
+    let body = spec.strip_prefix('/')?;
+    let mut pattern = String::new();
+    let mut chars = body.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '/' => return Some((pattern, &body[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '/')) => pattern.push('/'),
+                Some((_, other)) => {
+                    pattern.push('\\');
+                    pattern.push(other);
+                }
+                None => pattern.push('\\'),
+            },
+            c => pattern.push(c),
+        }
+    }
+    None
+}
+
+impl LineTarget {
+    /// The 1-based line numbers this target covers in the newest version of `file_path`.
+    pub fn resolve(&self, backend: &dyn GitBackend, file_path: &str) -> Result<(usize, usize)> {
+        match self {
+            LineTarget::Lines { start, end } => Ok((*start, *end)),
+            _ => self.resolve_in(&get_file_content(backend, "HEAD", file_path)?),
+        }
+    }
+
+    /// The 1-based line numbers this target covers in `contents`, found the way
+    /// `git log -L` finds them.
+    ///
+    /// ```
+    /// use garch::LineTarget;
+    ///
+    /// let contents = "import os\n\ndef load(path):\n    return open(path).read()\n\ndef save(path):\n    pass\n";
+    /// assert_eq!(LineTarget::Function("load".into()).resolve_in(contents), Ok((3, 5)));
+    /// let (_, targets) = garch::parse_file_range("app.py:/^def load/,/^def /,7").unwrap();
+    /// assert_eq!(targets[0].resolve_in(contents), Ok((3, 6)));
+    /// assert_eq!(targets[1].resolve_in(contents), Ok((7, 7)));
+    /// ```
+    pub fn resolve_in(&self, contents: &str) -> Result<(usize, usize)> {
+        let lines: Vec<&str> = contents.lines().collect();
+        match self {
+            LineTarget::Lines { start, end } => Ok((*start, *end)),
+            LineTarget::Function(name) => {
+                let pattern = compile(name)?;
+                let start = lines.iter()
+                    .position(|line| is_function_line(line) && pattern.is_match(line))
+                    .ok_or_else(|| Error::InvalidRange(format!("no function matching '{}'", name)))?;
+                // The function runs up to the line before the next one begins
+                let end = lines[start + 1..].iter()
+                    .position(|line| is_function_line(line))
+                    .map_or(lines.len(), |offset| start + 1 + offset);
+                Ok((start + 1, end))
+            }
+            LineTarget::Pattern { start: start_pattern, end } => {
+                let pattern = compile(start_pattern)?;
+                let start = lines.iter()
+                    .position(|line| pattern.is_match(line))
+                    .ok_or_else(|| Error::InvalidRange(format!("no line matching /{}/", start_pattern)))?;
+                let end = match end {
+                    PatternEnd::Pattern(end_pattern) => {
+                        let pattern = compile(end_pattern)?;
+                        let offset = lines[start + 1..].iter()
+                            .position(|line| pattern.is_match(line))
+                            .ok_or_else(|| Error::InvalidRange(format!("no line matching /{}/ after line {}", end_pattern, start + 1)))?;
+                        start + 1 + offset + 1
+                    }
+                    PatternEnd::Count(count) => (start + count).min(lines.len()),
+                    PatternEnd::EndOfFile => lines.len(),
+                };
+                Ok((start + 1, end))
+            }
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| Error::InvalidArgument(format!("Invalid pattern '{}': {}", pattern, e)))
+}
+
+/// Whether `line` starts a function by git's default rule, used when no diff driver
+/// says otherwise: it begins with a letter, `_` or `$`.
+fn is_function_line(line: &str) -> bool {
+    line.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+}
@@ -0,0 +1,1470 @@
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton, MouseEventKind},
+    execute,
+    style::{Color, ResetColor, SetForegroundColor, SetBackgroundColor},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use garch::blame::abbreviate_author;
+use garch::date::parse_moment;
+use garch::diff::{align_lines, word_diff};
+use garch::{
+    get_commit, get_pickaxe_history, get_tags, AlignedRow, BlameLine, ChangeType, CommitInfo, DateFormat, DiffHunk, DiffLine, DisplayZone, Error, FileVersion,
+    LazyVersions, Tag, VersionState,
+};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+/// `text` cut to `width` columns, with an ellipsis when anything was dropped.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(width.saturating_sub(3)).collect();
+    format!("{}...", kept)
+}
+
+fn get_author_color(author: &str) -> Color {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    
+    let mut hasher = DefaultHasher::new();
+    author.hash(&mut hasher);
+    let hash = hasher.finish();
+    
+    let colors = [
+        Color::Red,
+        Color::DarkCyan,
+        Color::DarkGreen,
+        Color::DarkYellow,
+        Color::DarkBlue,
+        Color::DarkMagenta,
+        Color::DarkRed,
+    ];
+    colors[hash as usize % colors.len()]
+}
+
+/// What the viewer shows and in which order, as chosen on the command line.
+pub struct ViewerOptions {
+    /// Line ranges to show, each in a labelled block when there are several; empty shows
+    /// the whole file. Versions that know where these lines had moved show that instead.
+    pub ranges: Vec<(usize, usize)>,
+    /// Start with the newest version instead of the oldest.
+    pub reverse: bool,
+    /// Contents only, without author sections.
+    pub plain: bool,
+    /// Timezone dates are shown in at start-up; `z` cycles through the others.
+    pub zone: DisplayZone,
+    /// How dates are written at start-up; `d` cycles through the others.
+    pub date_format: DateFormat,
+    /// Search for this from the start, case included, moving to its first match.
+    pub search: Option<String>,
+    /// Whether `search` is a regular expression rather than a literal string.
+    pub regex: bool,
+}
+
+/// What the content area shows for the current version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// The file, with who last changed each line.
+    Blame,
+    /// The diff against the version before it.
+    Diff,
+    /// The whole file next to the base version: the marked one, or else the one before it.
+    Compare,
+}
+
+/// One row of the content area: a line of the file, or the label opening a range's block.
+/// In diff view, rows are hunk headers and the lines of each hunk instead, and when
+/// comparing they are the base version's line next to the current one's.
+enum Row<'v> {
+    Label(String),
+    /// A line, and whether the version's own commit changed it.
+    Line(&'v BlameLine, bool),
+    Hunk(&'v DiffHunk),
+    Change(&'v DiffLine),
+    Pair(Option<&'v BlameLine>, Option<&'v BlameLine>, Option<ChangeType>),
+}
+
+/// How a changed line is marked: its sign and colour, and the backgrounds of the line
+/// and of the words in it that changed.
+#[derive(Clone, Copy)]
+struct ChangeStyle {
+    sign: char,
+    color: Color,
+    background: Color,
+    word_background: Color,
+}
+
+const REMOVED: ChangeStyle = ChangeStyle {
+    sign: '-',
+    color: Color::Red,
+    background: Color::Rgb { r: 70, g: 0, b: 0 },
+    word_background: Color::Rgb { r: 150, g: 30, b: 30 },
+};
+
+const ADDED: ChangeStyle = ChangeStyle {
+    sign: '+',
+    color: Color::Green,
+    background: Color::Rgb { r: 0, g: 55, b: 0 },
+    word_background: Color::Rgb { r: 20, g: 120, b: 20 },
+};
+
+/// Backgrounds of search matches, and of the one last moved to.
+const MATCH_BACKGROUND: Color = Color::Rgb { r: 100, g: 80, b: 0 };
+const CURRENT_MATCH_BACKGROUND: Color = Color::Rgb { r: 190, g: 120, b: 0 };
+
+/// Background of the line number the cursor is on.
+const CURSOR_BACKGROUND: Color = Color::Grey;
+
+/// A search started with `/` or `?`, which stays active from version to version.
+struct Search {
+    query: String,
+    /// Started with `?`, so `n` moves up the file instead of down.
+    backward: bool,
+    /// The query is a regular expression rather than a literal string.
+    regex: bool,
+    /// Match case even when the query has no capitals, as `git log -S` does.
+    exact_case: bool,
+    /// Move to the first match once the version on screen has loaded.
+    seek: bool,
+    /// The row of the match last moved to, with that line's text to find it by in other versions.
+    current: Option<(usize, String)>,
+}
+
+impl Search {
+    /// A search typed at the prompt, which moves as it is typed rather than seeking.
+    fn typed(query: String, backward: bool) -> Self {
+        Search { query, backward, regex: false, exact_case: false, seek: false, current: None }
+    }
+
+    /// The query as a pattern: a literal, unless `regex` is set, that ignores case unless
+    /// it has capitals in it.
+    fn pattern(&self) -> Option<Regex> {
+        if self.query.is_empty() {
+            return None;
+        }
+        let case = if self.exact_case || self.query.chars().any(char::is_uppercase) { "" } else { "(?i)" };
+        let query = if self.regex { self.query.clone() } else { regex::escape(&self.query) };
+        Regex::new(&format!("{}{}", case, query)).ok()
+    }
+
+    /// Find the current match again in `rows`, which may be another version's: on the same
+    /// row, or else on the nearest line with the same text. Returns its row when it moved;
+    /// the match is dropped when no line has that text any more.
+    fn relocate<'v>(&mut self, rows: &[Row<'v>]) -> Option<usize> {
+        let text = |row: &Row<'v>| -> Option<&'v str> {
+            match *row {
+                Row::Line(line, _) => Some(&line.content),
+                _ => None,
+            }
+        };
+        let (row, content) = self.current.as_mut()?;
+        if rows.get(*row).and_then(text) == Some(content.as_str()) {
+            return None;
+        }
+        let found = rows.iter()
+            .enumerate()
+            .filter(|(_, candidate)| text(candidate) == Some(content.as_str()))
+            .min_by_key(|(candidate, _)| candidate.abs_diff(*row))
+            .map(|(candidate, _)| candidate);
+        match found {
+            Some(found) => *row = found,
+            None => self.current = None,
+        }
+        found
+    }
+}
+
+/// The line the cursor was on when `,` moved to the version before the commit that last
+/// changed it, to be found there through that commit's diff.
+struct Landing {
+    /// Index into `history` of the version whose commit changed the line.
+    index: usize,
+    content: String,
+    line_number: usize,
+}
+
+/// What `p` found the last time it searched the file's history.
+struct Pickaxe {
+    query: String,
+    regex: bool,
+    /// The versions whose commits changed how often the query occurs, as indexes into `history`
+    versions: Result<Vec<usize>, Error>,
+}
+
+impl Pickaxe {
+    fn is_for(&self, search: &Search) -> bool {
+        self.query == search.query && self.regex == search.regex
+    }
+}
+
+/// Text being typed at the bottom of the screen, after the key that opened the prompt.
+struct Prompt {
+    key: char,
+    input: String,
+    /// Scroll position when the prompt opened, to go back to if it is cancelled.
+    origin: usize,
+    /// Where the cursor was then, to go back to as well.
+    cursor: usize,
+}
+
+/// Screen row of the timeline, below the header and commit lines; the row under it marks
+/// the current version and the tags.
+const TIMELINE_ROW: u16 = 2;
+
+/// How many versions either side of the current one are loaded in the background.
+const PREFETCH_DISTANCE: usize = 2;
+
+/// How often to check whether the version on screen has finished loading.
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Viewer<'a> {
+    history: &'a LazyVersions,
+    options: &'a ViewerOptions,
+    /// Position in display order, oldest first unless `options.reverse` is set
+    current_version: usize,
+    scroll_offset: usize,
+    zone: DisplayZone,
+    date_format: DateFormat,
+    show_details: bool,
+    view: View,
+    /// Index into `history` of the version marked as the base to compare against
+    marked: Option<usize>,
+    /// The last two versions compared, as indexes into `history`, and how their lines line up
+    alignment: Option<((usize, usize), Vec<AlignedRow>)>,
+    search: Option<Search>,
+    prompt: Option<Prompt>,
+    pickaxe: Option<Pickaxe>,
+    /// Row the cursor is on in blame view
+    cursor: usize,
+    /// Where `,` and `:` moved from, as position, scroll offset and cursor, for `<` to go back to
+    back: Vec<(usize, usize, usize)>,
+    /// Line to put the cursor on once the version on screen and the diff it is found through have loaded
+    landing: Option<Landing>,
+    /// Shown in the footer until the next key is pressed
+    notice: Option<String>,
+    /// Tags on the timeline, oldest first
+    tags: Vec<Tag>,
+    /// Column of each version on the last frame's timeline, by index into `history`
+    timeline: Vec<usize>,
+    /// Layout of the last frame drawn, used to bound scrolling
+    line_count: usize,
+    /// Rows of the last frame drawn where a block of changes starts
+    change_starts: Vec<usize>,
+    content_height: usize,
+}
+
+impl<'a> Viewer<'a> {
+    fn new(history: &'a LazyVersions, options: &'a ViewerOptions) -> Self {
+        Viewer {
+            history,
+            options,
+            current_version: 0,
+            scroll_offset: 0,
+            zone: options.zone,
+            date_format: options.date_format.clone(),
+            show_details: false,
+            view: View::Blame,
+            marked: None,
+            alignment: None,
+            search: options.search.clone().map(|query| Search {
+                query,
+                backward: false,
+                regex: options.regex,
+                exact_case: true,
+                seek: true,
+                current: None,
+            }),
+            prompt: None,
+            pickaxe: None,
+            cursor: 0,
+            back: Vec::new(),
+            landing: None,
+            notice: None,
+            // Without tags the timeline simply has none to show
+            tags: get_tags(history.backend()).unwrap_or_default(),
+            timeline: Vec::new(),
+            line_count: 0,
+            change_starts: Vec::new(),
+            content_height: 0,
+        }
+    }
+
+    /// Index into `history` (newest first) of the version at display `position`.
+    fn history_index(&self, position: usize) -> usize {
+        if self.options.reverse {
+            position
+        } else {
+            self.history.len() - 1 - position
+        }
+    }
+
+    /// Make sure the current version is being loaded, then queue up its neighbours.
+    fn request_current(&self) {
+        let index = self.history_index(self.current_version);
+        self.history.request(index);
+        if let Some(base) = self.base_index(index).filter(|_| self.view == View::Compare) {
+            self.history.request(base);
+        }
+        // Older neighbours first: newer versions can then derive their blame from them
+        for distance in 1..=PREFETCH_DISTANCE {
+            self.history.prefetch(index + distance);
+            if let Some(newer) = index.checked_sub(distance) {
+                self.history.prefetch(newer);
+            }
+        }
+    }
+
+    fn rows<'v>(&self, version: &'v FileVersion) -> Vec<Row<'v>> {
+        // Plain mode attributes every line to the version's commit, so none stands out
+        let line = |line: &'v BlameLine| Row::Line(line, !self.options.plain && version.changed_here(line));
+        let in_range = |(start_line, end_line): (usize, usize)| version.blame_lines.iter()
+            .filter(move |line| line.line_number >= start_line && line.line_number <= end_line)
+            .map(line);
+
+        match self.line_ranges(&version.commit).as_slice() {
+            // File command - show all lines
+            [] => version.blame_lines.iter().map(line).collect(),
+            // Lines command - filter to where the tracked lines were in this version
+            [range] => range.map(in_range).into_iter().flatten().collect(),
+            // Several ranges - one block each, labelled with the range as it was asked for
+            ranges => {
+                let mut rows = Vec::new();
+                for (&(start_line, end_line), range) in self.options.ranges.iter().zip(ranges) {
+                    let label = match range {
+                        Some(range) if *range == (start_line, end_line) => format!("Lines {}-{}", start_line, end_line),
+                        Some((start, end)) => format!("Lines {}-{} (at {}-{} in this version)", start_line, end_line, start, end),
+                        None => format!("Lines {}-{} (not in this version)", start_line, end_line),
+                    };
+                    rows.push(Row::Label(label));
+                    rows.extend(range.map(in_range).into_iter().flatten());
+                }
+                rows
+            }
+        }
+    }
+
+    /// Every hunk of a diff, each header followed by its lines.
+    fn diff_rows(hunks: &[DiffHunk]) -> Vec<Row<'_>> {
+        hunks.iter()
+            .flat_map(|hunk| std::iter::once(Row::Hunk(hunk)).chain(hunk.lines.iter().map(Row::Change)))
+            .collect()
+    }
+
+    /// The version to compare version `index` against: the marked one, or else its predecessor.
+    fn base_index(&self, index: usize) -> Option<usize> {
+        self.marked.or_else(|| (index + 1 < self.history.len()).then_some(index + 1))
+    }
+
+    /// Line up the two versions being compared, unless they already are.
+    fn align(&mut self, base: (usize, &FileVersion), current: (usize, &FileVersion)) {
+        if self.alignment.as_ref().is_some_and(|(indexes, _)| *indexes == (base.0, current.0)) {
+            return;
+        }
+        fn lines(version: &FileVersion) -> Vec<&str> {
+            version.blame_lines.iter().map(|line| line.content.as_str()).collect()
+        }
+        let rows = align_lines(&lines(base.1), &lines(current.1));
+        self.alignment = Some(((base.0, current.0), rows));
+    }
+
+    /// Where each requested range is in `commit`'s version; empty when the whole file is shown.
+    fn line_ranges(&self, commit: &CommitInfo) -> Vec<Option<(usize, usize)>> {
+        self.options.ranges.iter()
+            .enumerate()
+            .map(|(index, &range)| match commit.line_ranges.get(index) {
+                Some(known) => *known,
+                None => Some(range),
+            })
+            .collect()
+    }
+
+    /// How many of `rows`, starting at `from`, fit on screen along with the author
+    /// headers drawn above them.
+    fn rows_shown(&self, rows: &[Row], from: usize) -> usize {
+        let mut last_author = "";
+        let mut lines_used = 0;
+        let mut shown = 0;
+        for row in rows.iter().skip(from) {
+            lines_used += match row {
+                Row::Line(line, _) if !self.options.plain && line.author != last_author => {
+                    last_author = &line.author;
+                    2
+                }
+                Row::Label(_) => {
+                    last_author = "";
+                    1
+                }
+                _ => 1,
+            };
+            if lines_used > self.content_height {
+                break;
+            }
+            shown += 1;
+        }
+        shown
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.line_count.saturating_sub(self.content_height)
+    }
+
+    /// Draw the current version, or a placeholder while it loads. Returns whether the
+    /// version was ready, so the caller knows to redraw once it is.
+    fn draw(&mut self, stdout: &mut io::Stdout) -> io::Result<bool> {
+        let (terminal_width, terminal_height) = crossterm::terminal::size()?;
+        let index = self.history_index(self.current_version);
+        let commit = self.history.commit(index);
+
+        // The details pane takes at most half of the space below the header
+        let mut details = if self.show_details { self.commit_details(commit, terminal_width as usize) } else { Vec::new() };
+        details.truncate((terminal_height as usize).saturating_sub(5) / 2);
+        self.content_height = (terminal_height as usize).saturating_sub(5 + details.len()); // Reserve space for 4-line header + 1-line footer
+        let state = self.history.state(index);
+        let version = match &state {
+            VersionState::Ready(version) => Some(version.as_ref()),
+            _ => None,
+        };
+        let base = self.base_index(index).filter(|_| self.view == View::Compare);
+        let base_state = base.map(|base| self.history.state(base));
+        let base_version = match &base_state {
+            Some(VersionState::Ready(version)) => Some(version.as_ref()),
+            _ => None,
+        };
+        if let (Some(base), Some(base_version), Some(version)) = (base, base_version, version) {
+            self.align((base, base_version), (index, version));
+        }
+
+        let diff = if self.view == View::Diff { self.history.diff(index) } else { None };
+        let rows = match self.view {
+            View::Blame => version.map(|version| self.rows(version)).unwrap_or_default(),
+            View::Diff => diff.as_ref().and_then(|diff| diff.as_ref().ok()).map(|hunks| Self::diff_rows(hunks)).unwrap_or_default(),
+            View::Compare => match (base_version, version, &self.alignment) {
+                (Some(base_version), Some(version), Some((_, aligned))) => aligned.iter()
+                    .map(|row| Row::Pair(
+                        row.old.map(|line| &base_version.blame_lines[line]),
+                        row.new.map(|line| &version.blame_lines[line]),
+                        row.change_type,
+                    ))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        };
+        self.line_count = rows.len();
+        // Changed lines in blame view, hunks in diff view, and changed blocks when comparing
+        let is_change = |row: &Row| matches!(row, Row::Line(_, true) | Row::Hunk(_) | Row::Pair(_, _, Some(_)));
+        self.change_starts = rows.iter()
+            .enumerate()
+            .filter(|&(i, row)| is_change(row) && !(i > 0 && is_change(&rows[i - 1])))
+            .map(|(i, _)| i)
+            .collect();
+
+        // What to show instead of the rows, if they can't be shown yet or there are none
+        let waiting_on = match self.view {
+            View::Blame => vec![&state],
+            View::Diff => Vec::new(),
+            View::Compare => base_state.iter().chain([&state]).collect(),
+        };
+        let waiting = waiting_on.iter().any(|state| matches!(state, VersionState::Pending | VersionState::Loading))
+            || (self.view == View::Diff && diff.is_none());
+        let message = match (self.view, &diff) {
+            (View::Diff, None) => Some((Color::DarkGrey, "Loading diff...".to_string())),
+            (View::Diff, Some(Err(e))) => Some((Color::Red, format!("Failed to load the diff: {}", e))),
+            (View::Diff, _) if rows.is_empty() => Some((Color::DarkGrey, "No changes to the file's contents in this commit".to_string())),
+            (View::Compare, _) if base.is_none() => {
+                Some((Color::DarkGrey, "Nothing to compare with: mark a version with m, then move to another".to_string()))
+            }
+            _ => match waiting_on.iter().find_map(|state| match state {
+                VersionState::Failed(e) => Some(e),
+                _ => None,
+            }) {
+                Some(e) => Some((Color::Red, format!("Failed to load this version: {}", e))),
+                None if waiting => Some((Color::DarkGrey, "Loading version...".to_string())),
+                None if rows.is_empty() && self.view == View::Blame && !self.options.ranges.is_empty() => {
+                    let (start_line, end_line) = self.options.ranges[0];
+                    Some((Color::DarkGrey, format!("Lines {}-{} don't exist in this version", start_line, end_line)))
+                }
+                None => None,
+            },
+        };
+
+        // Keep the current match in view on another version, as long as its line is still
+        // there, or move to the first match where the search asks to
+        if let (View::Blame, Some(search), Some(_)) = (self.view, &mut self.search, version) {
+            if search.seek {
+                search.seek = false;
+                let pattern = search.pattern();
+                search.current = rows.iter().enumerate().find_map(|(row, line)| match line {
+                    Row::Line(line, _) if pattern.as_ref().is_some_and(|pattern| pattern.is_match(&line.content)) => {
+                        Some((row, line.content.clone()))
+                    }
+                    _ => None,
+                });
+                if let Some((row, _)) = &search.current {
+                    self.scroll_offset = reveal(self.scroll_offset, self.content_height, *row);
+                    self.cursor = *row;
+                }
+            } else if let Some(row) = search.relocate(&rows) {
+                self.scroll_offset = reveal(self.scroll_offset, self.content_height, row);
+                self.cursor = row;
+            }
+        }
+
+        // After `,`, put the cursor on the line it was looking for, or the nearest one shown;
+        // the commit's own diff says where the line was before it, failing that keep the number
+        let landing = self.landing.as_ref()
+            .filter(|_| self.view == View::Blame && version.is_some())
+            .and_then(|landing| match self.history.diff(landing.index)? {
+                Ok(hunks) => Some(line_before(&hunks, &landing.content, landing.line_number).unwrap_or(landing.line_number)),
+                Err(_) => Some(landing.line_number),
+            });
+        if let Some(line_number) = landing {
+            self.landing = None;
+            self.cursor = rows.iter()
+                .enumerate()
+                .filter_map(|(row, line)| match line {
+                    Row::Line(line, _) => Some((row, line.line_number.abs_diff(line_number))),
+                    _ => None,
+                })
+                .min_by_key(|&(_, distance)| distance)
+                .map_or(0, |(row, _)| row);
+            self.scroll_offset = self.cursor.saturating_sub(self.content_height / 2);
+        }
+
+        // Ensure scroll_offset is within valid bounds - this is critical!
+        // Versions that haven't loaded yet keep the position for when they have
+        if !waiting {
+            self.scroll_offset = self.scroll_offset.min(self.max_scroll());
+        }
+
+        // The cursor stays on a row, and the rows scroll to keep it on screen; author
+        // headers take up room too, so count the rows that fit rather than assume
+        if self.view == View::Blame && !waiting {
+            self.cursor = self.cursor.min(rows.len().saturating_sub(1));
+            self.scroll_offset = self.scroll_offset.min(self.cursor);
+            while self.cursor >= self.scroll_offset + self.rows_shown(&rows, self.scroll_offset).max(1) {
+                self.scroll_offset += 1;
+            }
+        }
+
+        // Move to top and clear the screen - but do it all at once
+        execute!(stdout, crossterm::cursor::MoveTo(0, 0))?;
+        execute!(stdout, crossterm::terminal::Clear(crossterm::terminal::ClearType::All))?;
+
+        // Header with colors
+        execute!(stdout, SetForegroundColor(Color::White), SetBackgroundColor(Color::DarkBlue))?;
+
+        // Main header line with file, version number, and date; when comparing, the
+        // position of the base version comes first
+        let position = match base {
+            Some(base) => format!("{} ↔ {}", self.history_index(base) + 1, self.current_version + 1),
+            None if self.marked == Some(index) => format!("{} (marked)", self.current_version + 1),
+            None => (self.current_version + 1).to_string(),
+        };
+        let header_text = format!("{} | {} of {} | {} ({})",
+            self.header_path(index),
+            position,
+            self.history.len(),
+            self.date_format.format(&commit.time, self.zone),
+            self.zone.name()
+        );
+        print!("{}", header_text);
+
+        // Pad to full width
+        if header_text.len() < terminal_width as usize {
+            print!("{}", " ".repeat(terminal_width as usize - header_text.len()));
+        }
+        execute!(stdout, ResetColor)?;
+        println!("\r");
+
+        // Commit details line
+        execute!(stdout, SetForegroundColor(Color::Yellow))?;
+        let describe = |commit: &CommitInfo| format!("{} | {}", commit.hash.chars().take(8).collect::<String>(), commit.message);
+        let commit_line = match base {
+            Some(base) => format!("{}  ↔  {}", describe(self.history.commit(base)), describe(commit)),
+            None => describe(commit),
+        };
+
+        // Truncate commit message if too long
+        let display_commit_line = truncate(&commit_line, terminal_width as usize);
+        let commit_line_width = display_commit_line.chars().count();
+
+        print!("{}", display_commit_line);
+        if commit_line_width < terminal_width as usize {
+            print!("{}", " ".repeat(terminal_width as usize - commit_line_width));
+        }
+        execute!(stdout, ResetColor)?;
+        println!("\r");
+
+        self.timeline = self.timeline_columns(terminal_width as usize);
+        self.draw_timeline(stdout, index, terminal_width as usize)?;
+
+        // Commit details pane, closed off by its own separator
+        for (color, text) in &details {
+            execute!(stdout, SetForegroundColor(*color))?;
+            print!("{}", truncate(text, terminal_width as usize));
+            execute!(stdout, ResetColor)?;
+            println!("\r");
+        }
+
+        // Ensure we're in a clean state before drawing content
+        execute!(stdout, ResetColor, SetBackgroundColor(Color::Reset))?;
+
+        match message {
+            Some((color, text)) => {
+                execute!(stdout, SetForegroundColor(color))?;
+                println!("{}\r", text);
+                execute!(stdout, ResetColor)?;
+            }
+            None => self.draw_lines(stdout, &rows, terminal_width as usize)?,
+        }
+
+        // Footer with colors
+        execute!(stdout, crossterm::cursor::MoveTo(0, terminal_height - 1))?;
+        execute!(stdout, SetForegroundColor(Color::White), SetBackgroundColor(Color::DarkGrey))?;
+        let (tab, compare) = match self.view {
+            View::Diff => ("Blame", "Compare"),
+            View::Compare => ("Diff", "Blame"),
+            View::Blame => ("Diff", "Compare"),
+        };
+        let footer_text = match (&self.prompt, &self.search) {
+            (Some(prompt), _) => format!("{}{}█", prompt.key, prompt.input),
+            (None, _) if self.notice.is_some() => self.notice.clone().unwrap_or_default(),
+            (None, Some(search)) => {
+                // What `p` found for this search, if it has been asked
+                let changed = match self.pickaxe.as_ref().filter(|pickaxe| pickaxe.is_for(search)).map(|pickaxe| &pickaxe.versions) {
+                    Some(Ok(versions)) => format!(" [added or removed in {} version(s)]", versions.len()),
+                    Some(Err(e)) => format!(" [{}]", e),
+                    None => String::new(),
+                };
+                format!("{}{}{} │ n N : Next/Previous match │ p P : Where it changed │ Esc : Clear │ ← Older    Newer → │ ↑ ↓ : Scroll │ q : Quit",
+                    if search.backward { '?' } else { '/' }, search.query, changed)
+            }
+            (None, None) => format!("← Older    Newer → │ ↑ ↓ : Scroll │ Mouse: Scroll │ / ? : Search │ : : Jump │ [ ] : Changes │ , < : Blame parent/Back │ Tab : {} │ m : Mark │ c : {} │ i : Details │ d : Dates │ z : Timezone │ q : Quit", tab, compare),
+        };
+        let footer_text = truncate(&footer_text, terminal_width as usize);
+        print!("{}", footer_text);
+        // Pad footer to full width
+        let footer_width = footer_text.chars().count();
+        if footer_width < terminal_width as usize {
+            print!("{}", " ".repeat(terminal_width as usize - footer_width));
+        }
+        execute!(stdout, ResetColor)?;
+        print!("\r");
+        stdout.flush()?;
+
+        Ok(!waiting && self.landing.is_none())
+    }
+
+    /// The file's name at version `index`, with the lines shown of it, noting the rename
+    /// when the version before it had a different name.
+    fn header_path(&self, index: usize) -> String {
+        let file_path = self.history.file_path();
+        let commit = self.history.commit(index);
+        let path = commit.path_or(file_path);
+        let ranges: Vec<String> = self.line_ranges(commit)
+            .into_iter()
+            .map(|range| match range {
+                Some((start_line, end_line)) => format!("{}-{}", start_line, end_line),
+                None => "-".to_string(),
+            })
+            .collect();
+        let range = if ranges.is_empty() { String::new() } else { format!(":{}", ranges.join(",")) };
+        match (index + 1 < self.history.len()).then(|| self.history.commit(index + 1).path_or(file_path)) {
+            Some(older) if older != path => format!("{}{} (renamed from {})", path, range, older),
+            _ => format!("{}{}", path, range),
+        }
+    }
+
+    /// Lines of the commit details pane: full hash and parents, author and committer
+    /// with their dates, then the whole message with its trailers set apart.
+    fn commit_details(&self, commit: &CommitInfo, width: usize) -> Vec<(Color, String)> {
+        let short = |hash: &String| hash.chars().take(8).collect::<String>();
+        let parents: Vec<String> = commit.parents.iter().map(short).collect();
+        let mut lines = vec![
+            (Color::Yellow, format!("commit    {}", commit.hash)),
+            (Color::DarkGrey, format!("parents   {}", if parents.is_empty() { "(root commit)".to_string() } else { parents.join(" ") })),
+            (Color::White, format!("author    {} <{}>  {}",
+                commit.author, commit.author_email, self.date_format.format(&commit.time, self.zone))),
+            (Color::White, format!("committer {} <{}>  {}",
+                commit.committer, commit.committer_email, self.date_format.format(&commit.committer_time, self.zone))),
+            (Color::Reset, String::new()),
+            (Color::White, format!("    {}", commit.message)),
+        ];
+
+        // The trailers are the body's last paragraph; they get their own colour
+        let mut body = commit.body.as_str();
+        if !commit.trailers.is_empty() {
+            body = body.rsplit_once("\n\n").map(|(text, _)| text).unwrap_or("");
+        }
+        if !body.is_empty() {
+            lines.push((Color::Reset, String::new()));
+            lines.extend(body.lines().map(|line| (Color::Reset, format!("    {}", line))));
+        }
+        if !commit.trailers.is_empty() {
+            lines.push((Color::Reset, String::new()));
+            lines.extend(commit.trailers.iter().map(|(key, value)| (Color::DarkCyan, format!("    {}: {}", key, value))));
+        }
+
+        lines.push((Color::DarkGrey, "─".repeat(width)));
+        lines
+    }
+
+    /// Switch to the next date format, including the custom one given on the command line.
+    fn cycle_date_format(&mut self) {
+        let mut formats = vec![DateFormat::Short, DateFormat::Relative, DateFormat::Iso, DateFormat::Full];
+        if let DateFormat::Custom(_) = self.options.date_format {
+            formats.push(self.options.date_format.clone());
+        }
+        let current = formats.iter().position(|format| *format == self.date_format).unwrap_or(0);
+        self.date_format = formats[(current + 1) % formats.len()].clone();
+    }
+
+    fn draw_lines(&self, stdout: &mut io::Stdout, rows: &[Row], terminal_width: usize) -> io::Result<()> {
+        let pattern = self.search.as_ref().and_then(Search::pattern);
+        let current_match = self.search.as_ref().and_then(|search| search.current.as_ref()).map(|(row, _)| *row);
+        let display_end = (self.scroll_offset + self.content_height).min(rows.len());
+        let mut last_author = String::new();
+        let content_width = terminal_width.saturating_sub(20); // Reserve space for line numbers and margins
+        let mut lines_displayed = 0; // Track actual screen lines used
+
+        for i in self.scroll_offset..display_end {
+            if let Some(Row::Label(label)) = rows.get(i) {
+                if lines_displayed + 1 > self.content_height {
+                    break;
+                }
+                // A separator naming the block below; its first author gets a header of their own
+                let label = format!("── {} ", label);
+                execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                print!("{}", label);
+                println!("{}\r", "─".repeat(terminal_width.saturating_sub(label.chars().count())));
+                execute!(stdout, ResetColor)?;
+                last_author.clear();
+                lines_displayed += 1;
+            } else if let Some(Row::Line(line, changed)) = rows.get(i) {
+                // Check if we need to show author info (first line or author changed)
+                // Plain mode has no authorship to show, only the text
+                let show_author = !self.options.plain && last_author != line.author;
+
+                // Stop if we would exceed screen space (accounting for author headers)
+                let lines_needed = if show_author { 2 } else { 1 }; // Author header + content line
+                if lines_displayed + lines_needed > self.content_height {
+                    break;
+                }
+
+                if show_author {
+                    last_author = line.author.clone();
+
+                    // Author header line with color
+                    let author_color = get_author_color(&line.author);
+                    execute!(stdout, SetForegroundColor(author_color))?;
+                    print!("┌─ {} ", line.author);
+                    execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                    print!("({}) ", self.date_format.format(&line.author_time, self.zone));
+                    execute!(stdout, SetForegroundColor(Color::Yellow))?;
+                    print!("[{}] ", line.commit_hash);
+                    execute!(stdout, SetForegroundColor(Color::White))?;
+                    print!("{}", line.commit_message);
+                    execute!(stdout, ResetColor)?;
+                    println!("\r");
+                    lines_displayed += 1;
+                }
+
+                // Line number with proper spacing, marked in the gutter when this commit changed it
+                if *changed {
+                    execute!(stdout, SetForegroundColor(Color::Green))?;
+                    print!("┃");
+                } else {
+                    execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                    print!("│");
+                }
+                if i == self.cursor {
+                    execute!(stdout, SetForegroundColor(Color::Black), SetBackgroundColor(CURSOR_BACKGROUND))?;
+                } else {
+                    execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                }
+                print!(" {:3} ", line.line_number);
+                execute!(stdout, ResetColor, SetForegroundColor(Color::DarkGrey))?;
+                print!("│ ");
+                execute!(stdout, ResetColor)?;
+
+                // Content with line wrapping - use pre-rendered highlighted content
+                let content = &line.content;
+                let text = if content.len() <= content_width {
+                    // Single line - no wrapping needed
+                    line.highlighted_content.clone()
+                } else {
+                    // For long lines, just truncate to avoid wrapping complexity with ANSI codes
+                    // Use plain content for truncation to avoid cutting ANSI escape sequences
+                    truncate(content, content_width)
+                };
+
+                // Search matches are picked out on top of the syntax highlighting
+                match &pattern {
+                    Some(pattern) => {
+                        let matches: Vec<Range<usize>> = pattern.find_iter(content).map(|found| found.range()).collect();
+                        let background = if current_match == Some(i) { CURRENT_MATCH_BACKGROUND } else { MATCH_BACKGROUND };
+                        println!("{}\r", emphasize(&text, &matches, background, Color::Reset));
+                    }
+                    None => println!("{}\r", text),
+                }
+                lines_displayed += 1;
+            } else if let Some(Row::Hunk(hunk)) = rows.get(i) {
+                if lines_displayed + 1 > self.content_height {
+                    break;
+                }
+                execute!(stdout, SetForegroundColor(Color::Cyan))?;
+                print!("{}", truncate(&hunk.header, terminal_width));
+                execute!(stdout, ResetColor)?;
+                println!("\r");
+                lines_displayed += 1;
+            } else if let Some(Row::Change(line)) = rows.get(i) {
+                if lines_displayed + 1 > self.content_height {
+                    break;
+                }
+                self.draw_change(stdout, line, content_width)?;
+                lines_displayed += 1;
+            } else if let Some(Row::Pair(old, new, change_type)) = rows.get(i) {
+                if lines_displayed + 1 > self.content_height {
+                    break;
+                }
+                // Both panes scroll together, since every row holds a line of each
+                let pane_width = terminal_width.saturating_sub(1) / 2;
+                let changed = change_type.is_some();
+                let words = match (old, new) {
+                    (Some(old), Some(new)) if changed => word_diff(&old.content, &new.content),
+                    _ => Default::default(),
+                };
+                draw_pane(stdout, *old, changed.then_some(REMOVED), &words.old, pane_width)?;
+                execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                print!("│");
+                draw_pane(stdout, *new, changed.then_some(ADDED), &words.new, pane_width)?;
+                execute!(stdout, ResetColor)?;
+                println!("\r");
+                lines_displayed += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// The column each version sits at on a timeline `width` wide, by index into `history`:
+    /// spaced by when their commits were made, or evenly if that was all at once.
+    fn timeline_columns(&self, width: usize) -> Vec<usize> {
+        let count = self.history.len();
+        let span = self.timeline_span();
+        if span.0 == span.1 && count > 1 {
+            return (0..count).map(|index| (count - 1 - index) * width.saturating_sub(1) / (count - 1)).collect();
+        }
+        (0..count).map(|index| timeline_column(self.history.commit(index).committer_time.timestamp(), span, width)).collect()
+    }
+
+    /// When the oldest and newest versions were committed, in seconds since the epoch.
+    fn timeline_span(&self) -> (i64, i64) {
+        // Commits are newest first, but their clocks needn't agree
+        let times = (0..self.history.len()).map(|index| self.history.commit(index).committer_time.timestamp());
+        (times.clone().min().unwrap_or(0), times.max().unwrap_or(0))
+    }
+
+    /// The timeline, which takes the place of the separator under the header: a tick for
+    /// every version in its author's colour, oldest on the left, then a row marking the
+    /// current version and naming the tags where they fall.
+    fn draw_timeline(&self, stdout: &mut io::Stdout, index: usize, width: usize) -> io::Result<()> {
+        if width == 0 {
+            return Ok(());
+        }
+        let mut strip = vec![('─', Color::DarkGrey); width];
+        let tick = |version: usize, c: char| (c, get_author_color(&abbreviate_author(&self.history.commit(version).author)));
+        // Older versions first, so the newest of those sharing a column is the one shown,
+        // except that the current version always is
+        for (version, &column) in self.timeline.iter().enumerate().rev() {
+            strip[column] = tick(version, '│');
+        }
+        strip[self.timeline[index]] = tick(index, '█');
+
+        let mut marks = vec![(' ', Color::Reset); width];
+        let current = self.timeline[index];
+        marks[current] = ('▲', Color::White);
+        // Tags on a version sit at its tick, others where their commit falls in time
+        let versions: HashMap<&str, usize> = (0..self.history.len())
+            .map(|version| (self.history.commit(version).hash.as_str(), version))
+            .collect();
+        let span = self.timeline_span();
+        let mut free_from = 0;
+        for tag in &self.tags {
+            let column = match versions.get(tag.commit.as_str()) {
+                Some(&version) => self.timeline[version],
+                None => timeline_column(tag.time.timestamp(), span, width),
+            };
+            // Names move beside the marker rather than cover it, and are left out where they
+            // would run into each other
+            let name: Vec<char> = tag.name.chars().collect();
+            let mut start = column.min(width.saturating_sub(name.len()));
+            if (start..start + name.len()).contains(&current) {
+                start = if current + 1 + name.len() <= width { current + 1 } else { current.saturating_sub(name.len()) };
+            }
+            let end = start + name.len();
+            if start < free_from || end > width || (start..end).contains(&current) {
+                continue;
+            }
+            for (offset, &c) in name.iter().enumerate() {
+                marks[start + offset] = (c, Color::Yellow);
+            }
+            free_from = end + 1;
+        }
+
+        for row in [strip, marks] {
+            let mut last_color = None;
+            for (c, color) in row {
+                if last_color != Some(color) {
+                    execute!(stdout, SetForegroundColor(color))?;
+                    last_color = Some(color);
+                }
+                print!("{}", c);
+            }
+            execute!(stdout, ResetColor)?;
+            println!("\r");
+        }
+        Ok(())
+    }
+
+    /// A click or drag on the timeline: move to the version whose tick is nearest `column`.
+    fn select_on_timeline(&mut self, column: usize) {
+        if let Some(index) = (0..self.timeline.len()).min_by_key(|&index| self.timeline[index].abs_diff(column)) {
+            self.current_version = self.history_index(index);
+        }
+    }
+
+    /// One line of a diff hunk: its numbers on either side, then the line itself, on a
+    /// green or red background when it was added or removed, with any words that differ
+    /// from the line it pairs with picked out.
+    fn draw_change(&self, stdout: &mut io::Stdout, line: &DiffLine, content_width: usize) -> io::Result<()> {
+        let number = |number: usize, shown: bool| if shown { format!("{:4}", number) } else { "    ".to_string() };
+        let style = match line.change_type {
+            Some(ChangeType::Added | ChangeType::Modified) => Some(ADDED),
+            Some(ChangeType::Removed) => Some(REMOVED),
+            None => None,
+        };
+
+        execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+        print!("│ {} {} │", number(line.old_line, line.change_type != Some(ChangeType::Added)),
+            number(line.new_line, line.change_type != Some(ChangeType::Removed)));
+        execute!(stdout, SetForegroundColor(style.map_or(Color::DarkGrey, |style| style.color)))?;
+        print!("{} ", style.map_or(' ', |style| style.sign));
+        if let Some(style) = style {
+            execute!(stdout, SetBackgroundColor(style.background))?;
+        }
+
+        // Same as blame view: long lines are cut from the plain text, so no escape is split
+        let text = if line.content.len() <= content_width {
+            line.highlighted_content.clone()
+        } else {
+            truncate(&line.content, content_width)
+        };
+        match (style, &line.changed_ranges) {
+            (Some(style), Some(ranges)) => print!("{}", emphasize(&text, ranges, style.word_background, style.background)),
+            _ => print!("{}", text),
+        }
+        execute!(stdout, ResetColor)?;
+        println!("\r");
+        Ok(())
+    }
+
+    /// Rows of the current version in blame view whose line contains the search, each with
+    /// that line's text.
+    fn search_matches(&self) -> Vec<(usize, String)> {
+        let Some(pattern) = self.search.as_ref().and_then(Search::pattern).filter(|_| self.view == View::Blame) else {
+            return Vec::new();
+        };
+        let Some(version) = self.history.get(self.history_index(self.current_version)) else {
+            return Vec::new();
+        };
+        self.rows(&version)
+            .iter()
+            .enumerate()
+            .filter_map(|(row, line)| match line {
+                Row::Line(line, _) if pattern.is_match(&line.content) => Some((row, line.content.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Move to the first match after row `from`, or before it when `backward`, wrapping
+    /// around the end of the file. A match on `from` itself counts when `inclusive`.
+    fn jump_to_match(&mut self, from: usize, backward: bool, inclusive: bool) {
+        let matches = self.search_matches();
+        let found = if backward {
+            matches.iter().rev().find(|(row, _)| *row < from || (inclusive && *row == from)).or(matches.last())
+        } else {
+            matches.iter().find(|(row, _)| *row > from || (inclusive && *row == from)).or(matches.first())
+        };
+        let found = found.cloned();
+        if let Some(search) = &mut self.search {
+            if let Some((row, _)) = &found {
+                self.scroll_offset = reveal(self.scroll_offset, self.content_height, *row);
+                self.cursor = *row;
+            }
+            search.current = found;
+        }
+    }
+
+    /// `n` or `N`: the next match in the search's own direction, or the other way when `reverse`.
+    fn next_match(&mut self, reverse: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let backward = search.backward != reverse;
+        match search.current.as_ref().map(|(row, _)| *row) {
+            Some(row) => self.jump_to_match(row, backward, false),
+            None => self.jump_to_match(self.scroll_offset, backward, true),
+        }
+    }
+
+    /// `p` or `P`: the next or previous version whose commit changed how often the search
+    /// occurs in the file. The first time for a search, history is searched and the first
+    /// such version opened.
+    fn next_pickaxe(&mut self, backward: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let fresh = !self.pickaxe.as_ref().is_some_and(|pickaxe| pickaxe.is_for(search));
+        if fresh {
+            let versions = get_pickaxe_history(self.history.backend(), self.history.file_path(), &search.query, search.regex).map(|commits| {
+                let hashes: HashSet<&str> = commits.iter().map(|commit| commit.hash.as_str()).collect();
+                (0..self.history.len()).filter(|&index| hashes.contains(self.history.commit(index).hash.as_str())).collect()
+            });
+            self.pickaxe = Some(Pickaxe { query: search.query.clone(), regex: search.regex, versions });
+        }
+        let Some(Pickaxe { versions: Ok(versions), .. }) = &self.pickaxe else {
+            return;
+        };
+
+        // history_index maps display positions to indexes and back again
+        let mut positions: Vec<usize> = versions.iter().map(|&index| self.history_index(index)).collect();
+        positions.sort_unstable();
+        let target = if fresh {
+            positions.first()
+        } else if backward {
+            positions.iter().rev().find(|&&position| position < self.current_version)
+        } else {
+            positions.iter().find(|&&position| position > self.current_version)
+        };
+        if let (Some(&target), Some(search)) = (target, &mut self.search) {
+            self.current_version = target;
+            // Highlight exactly what git counted
+            search.exact_case = true;
+            search.seek = true;
+            search.current = None;
+        }
+    }
+
+    /// `,`: move to the version before the commit that last changed the line under the
+    /// cursor, with the cursor on the line it replaced there, remembering where to come
+    /// back to with `<`.
+    fn blame_parent(&mut self) {
+        let Some(version) = self.history.get(self.history_index(self.current_version)).filter(|_| self.view == View::Blame) else {
+            return;
+        };
+        let Some(Row::Line(line, _)) = self.rows(&version).into_iter().nth(self.cursor) else {
+            return;
+        };
+        if self.options.plain || line.commit_hash.is_empty() {
+            self.notice = Some("No blame to follow without authorship".to_string());
+            return;
+        }
+        let Some(commit) = (0..self.history.len()).find(|&index| self.history.commit(index).hash.starts_with(&line.commit_hash)) else {
+            self.notice = Some(format!("Commit {} isn't one of the versions here", line.commit_hash));
+            return;
+        };
+        if commit + 1 == self.history.len() {
+            self.notice = Some(format!("Line {} has been there since the first version", line.line_number));
+            return;
+        }
+
+        // The line is found once the commit's diff has loaded, which starts now
+        self.history.diff(commit);
+        self.back.push((self.current_version, self.scroll_offset, self.cursor));
+        self.current_version = self.history_index(commit + 1);
+        self.landing = Some(Landing { index: commit, content: line.content.clone(), line_number: line.line_number });
+    }
+
+    /// `:`: move to the version `target` names, remembering where to come back to with `<`.
+    fn jump_to(&mut self, target: &str) {
+        if target.is_empty() {
+            return;
+        }
+        match self.find_version(target) {
+            Ok(index) => {
+                self.back.push((self.current_version, self.scroll_offset, self.cursor));
+                self.current_version = self.history_index(index);
+            }
+            Err(message) => self.notice = Some(message),
+        }
+    }
+
+    /// The index into `history` of the version `target` names: its number as the header
+    /// shows it, a prefix of its commit's hash, or the version in effect at a date or time
+    /// (on the clock dates are shown on) or at a commit, tag or branch that didn't change
+    /// the file.
+    fn find_version(&self, target: &str) -> Result<usize, String> {
+        let count = self.history.len();
+        if let Some(position) = target.parse::<usize>().ok().filter(|position| (1..=count).contains(position)) {
+            return Ok(self.history_index(position - 1));
+        }
+        let hash = target.to_ascii_lowercase();
+        if hash.len() >= 4 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            if let Some(index) = (0..count).find(|&index| self.history.commit(index).hash.starts_with(&hash)) {
+                return Ok(index);
+            }
+        }
+
+        // Versions are newest first, so the first one made by then is the one in effect
+        if let Some(moment) = parse_moment(target) {
+            return (0..count)
+                .find(|&index| self.zone.convert(&self.history.commit(index).time).naive_local() <= moment)
+                .ok_or_else(|| format!("No version of the file existed yet at {}", target));
+        }
+        let commit = get_commit(self.history.backend(), target)
+            .map_err(|_| format!("'{}' isn't a version number, commit, date, tag or branch", target))?;
+        (0..count)
+            .find(|&index| self.history.commit(index).hash == commit.hash)
+            .or_else(|| (0..count).find(|&index| self.history.commit(index).time <= commit.time))
+            .ok_or_else(|| format!("No version of the file existed yet at {}", target))
+    }
+
+    /// `<`: return to where the last `,` or `:` was used.
+    fn go_back(&mut self) {
+        match self.back.pop() {
+            Some((position, scroll_offset, cursor)) => {
+                self.current_version = position;
+                self.scroll_offset = scroll_offset;
+                self.cursor = cursor;
+                self.landing = None;
+            }
+            None => self.notice = Some("Nowhere to go back to".to_string()),
+        }
+    }
+
+    /// Apply a key typed while the prompt is open.
+    fn handle_prompt_key(&mut self, code: KeyCode) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        match code {
+            KeyCode::Enter if prompt.key == ':' => {
+                let target = prompt.input.trim().to_string();
+                self.prompt = None;
+                self.jump_to(&target);
+                return;
+            }
+            // Nothing moves while a `:` prompt is open, so there is nothing to undo
+            KeyCode::Esc if prompt.key == ':' => {
+                self.prompt = None;
+                return;
+            }
+            KeyCode::Backspace if prompt.key == ':' && prompt.input.is_empty() => {
+                self.prompt = None;
+                return;
+            }
+            KeyCode::Enter => {
+                self.prompt = None;
+                if self.search.as_ref().is_some_and(|search| search.query.is_empty()) {
+                    self.search = None;
+                }
+                return;
+            }
+            KeyCode::Esc => {
+                self.scroll_offset = prompt.origin;
+                self.cursor = prompt.cursor;
+                self.prompt = None;
+                self.search = None;
+                return;
+            }
+            KeyCode::Backspace if prompt.input.is_empty() => {
+                self.scroll_offset = prompt.origin;
+                self.cursor = prompt.cursor;
+                self.prompt = None;
+                self.search = None;
+                return;
+            }
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char(c) => prompt.input.push(c),
+            _ => return,
+        }
+        if prompt.key == ':' {
+            return;
+        }
+
+        // Search as the query is typed, from where the prompt was opened
+        let (query, backward, origin) = (prompt.input.clone(), prompt.key == '?', prompt.origin);
+        self.search = Some(Search::typed(query, backward));
+        self.scroll_offset = origin;
+        self.cursor = prompt.cursor;
+        self.jump_to_match(origin, backward, true);
+    }
+
+    /// Apply one input event. Returns `false` when the viewer should close.
+    fn handle_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press && self.prompt.is_some() => self.handle_prompt_key(key.code),
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                self.notice = None;
+                match key.code {
+                    KeyCode::Char('q') => return false,
+                    KeyCode::Char(key @ ('/' | '?' | ':')) => {
+                        self.prompt = Some(Prompt { key, input: String::new(), origin: self.scroll_offset, cursor: self.cursor });
+                    }
+                    KeyCode::Char('n') => self.next_match(false),
+                    KeyCode::Char('N') => self.next_match(true),
+                    KeyCode::Char('p') => self.next_pickaxe(false),
+                    KeyCode::Char('P') => self.next_pickaxe(true),
+                    KeyCode::Char(',') => self.blame_parent(),
+                    KeyCode::Char('<') => self.go_back(),
+                    KeyCode::Esc => self.search = None,
+                    KeyCode::Char('d') => self.cycle_date_format(),
+                    KeyCode::Char('i') => self.show_details = !self.show_details,
+                    KeyCode::Tab => self.view = if self.view == View::Diff { View::Blame } else { View::Diff },
+                    KeyCode::Char('c') => self.view = if self.view == View::Compare { View::Blame } else { View::Compare },
+                    KeyCode::Char('m') => {
+                        let index = self.history_index(self.current_version);
+                        self.marked = if self.marked == Some(index) { None } else { Some(index) };
+                    }
+                    KeyCode::Char('z') => {
+                        self.zone = match self.zone {
+                            DisplayZone::Author => DisplayZone::Local,
+                            DisplayZone::Local => DisplayZone::Utc,
+                            DisplayZone::Utc => DisplayZone::Author,
+                        };
+                    }
+                    KeyCode::Left => {
+                        // Don't reset scroll immediately - let the bounds checking handle it
+                        self.current_version = self.current_version.saturating_sub(1);
+                    }
+                    KeyCode::Right if self.current_version < self.history.len() - 1 => {
+                        self.current_version += 1;
+                        // Don't reset scroll immediately - let the bounds checking handle it
+                    }
+                    // The cursor moves in blame view, and the rows scroll along to keep it on screen
+                    KeyCode::Up if self.view == View::Blame => {
+                        self.cursor = self.cursor.saturating_sub(1);
+                    }
+                    KeyCode::Down if self.view == View::Blame => {
+                        self.cursor = (self.cursor + 1).min(self.line_count.saturating_sub(1));
+                    }
+                    KeyCode::Up => {
+                        self.scroll_offset = self.scroll_offset.saturating_sub(1);
+                    }
+                    KeyCode::Down if self.scroll_offset < self.max_scroll() => {
+                        self.scroll_offset += 1;
+                    }
+                    // Scrolling takes the cursor along, where it stays on the same screen line
+                    KeyCode::PageUp => {
+                        self.scroll_offset = self.scroll_offset.saturating_sub(self.content_height / 2);
+                        self.cursor = self.cursor.saturating_sub(self.content_height / 2);
+                    }
+                    KeyCode::PageDown => {
+                        self.scroll_offset = (self.scroll_offset + self.content_height / 2).min(self.max_scroll());
+                        self.cursor += self.content_height / 2;
+                    }
+                    KeyCode::Home => {
+                        self.scroll_offset = 0;
+                        self.cursor = 0;
+                    }
+                    // Changes are found from the cursor, or from the top of the screen in the
+                    // views without one
+                    KeyCode::Char(']') => {
+                        let from = if self.view == View::Blame { self.cursor } else { self.scroll_offset };
+                        if let Some(&start) = self.change_starts.iter().find(|&&start| start > from) {
+                            self.scroll_offset = start.min(self.max_scroll());
+                            self.cursor = start;
+                        }
+                    }
+                    KeyCode::Char('[') => {
+                        let from = if self.view == View::Blame { self.cursor } else { self.scroll_offset };
+                        if let Some(&start) = self.change_starts.iter().rev().find(|&&start| start < from) {
+                            self.scroll_offset = start.min(self.max_scroll());
+                            self.cursor = start;
+                        }
+                    }
+                    KeyCode::End => {
+                        self.scroll_offset = self.max_scroll();
+                        self.cursor = self.line_count.saturating_sub(1);
+                    }
+                    _ => {}
+                }
+            }
+            Event::Mouse(mouse) => {
+                match mouse.kind {
+                    MouseEventKind::ScrollUp => {
+                        self.scroll_offset = self.scroll_offset.saturating_sub(3); // Scroll 3 lines at a time
+                        self.cursor = self.cursor.saturating_sub(3);
+                    }
+                    MouseEventKind::ScrollDown => {
+                        self.scroll_offset = (self.scroll_offset + 3).min(self.max_scroll());
+                        self.cursor += 3;
+                    }
+                    MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left)
+                        if (TIMELINE_ROW..=TIMELINE_ROW + 1).contains(&mouse.row) =>
+                    {
+                        self.select_on_timeline(mouse.column as usize);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// One side of a comparison, `width` columns wide: the line's number and text, marked in
+/// `change`'s style when the line differs from the other side, with the byte ranges in
+/// `changed` picked out.
+fn draw_pane(stdout: &mut io::Stdout, line: Option<&BlameLine>, change: Option<ChangeStyle>, changed: &[Range<usize>], width: usize) -> io::Result<()> {
+    let Some(line) = line else {
+        print!("{}", " ".repeat(width));
+        return Ok(());
+    };
+    let text_width = width.saturating_sub(7);
+
+    execute!(stdout, SetForegroundColor(Color::DarkGrey))?;
+    print!("{:5} ", line.line_number);
+    execute!(stdout, SetForegroundColor(change.map_or(Color::DarkGrey, |style| style.color)))?;
+    print!("{}", change.map_or(' ', |style| style.sign));
+    execute!(stdout, ResetColor, SetBackgroundColor(change.map_or(Color::Reset, |style| style.background)))?;
+
+    // Long lines are cut from the plain text, so no escape is split
+    let length = line.content.chars().count();
+    let text = if length <= text_width { line.highlighted_content.clone() } else { truncate(&line.content, text_width) };
+    match change {
+        Some(style) => print!("{}", emphasize(&text, changed, style.word_background, style.background)),
+        None => print!("{}", text),
+    }
+    execute!(stdout, ResetColor)?;
+    print!("{}", " ".repeat(text_width.saturating_sub(length)));
+    Ok(())
+}
+
+/// `text`, plain or syntax highlighted, with the parts at `ranges` (byte ranges of its
+/// plain text) set on `background`, going back to `line_background` after each.
+fn emphasize(text: &str, ranges: &[Range<usize>], background: Color, line_background: Color) -> String {
+    let mut emphasized = String::new();
+    let mut plain = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Escape sequences take up no room in the plain text, so copy them through whole
+            emphasized.push(c);
+            for c in chars.by_ref() {
+                emphasized.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        if ranges.iter().any(|range| range.start == plain) {
+            emphasized.push_str(&SetBackgroundColor(background).to_string());
+        }
+        emphasized.push(c);
+        plain += c.len_utf8();
+        if ranges.iter().any(|range| range.end == plain) {
+            emphasized.push_str(&SetBackgroundColor(line_background).to_string());
+        }
+    }
+    emphasized
+}
+
+/// Where the line added as `content` by a commit's diff, nearest to line `near` of the new
+/// version, was before the commit: the removed line it took the place of, or else the
+/// line it was inserted before.
+fn line_before(hunks: &[DiffHunk], content: &str, near: usize) -> Option<usize> {
+    let mut best: Option<(usize, usize)> = None;
+    for hunk in hunks {
+        // Each run of added lines replaces the run of removed lines just before it, line for line
+        let mut removed = Vec::new();
+        let mut added = 0;
+        for line in &hunk.lines {
+            match line.change_type {
+                Some(ChangeType::Added) => {
+                    if line.content == content && best.is_none_or(|(new_line, _)| line.new_line.abs_diff(near) < new_line.abs_diff(near)) {
+                        best = Some((line.new_line, removed.get(added).copied().unwrap_or(line.old_line)));
+                    }
+                    added += 1;
+                }
+                Some(_) => {
+                    if added > 0 {
+                        removed.clear();
+                        added = 0;
+                    }
+                    removed.push(line.old_line);
+                }
+                None => {
+                    removed.clear();
+                    added = 0;
+                }
+            }
+        }
+    }
+    best.map(|(_, old_line)| old_line)
+}
+
+/// The column of a timeline `width` wide that `time` falls at, when it runs from the first
+/// to the last of `span` (seconds since the epoch); times outside that go at either end.
+fn timeline_column(time: i64, (first, last): (i64, i64), width: usize) -> usize {
+    let last_column = width.saturating_sub(1) as i64;
+    if last <= first {
+        return if time > first { last_column as usize } else { 0 };
+    }
+    ((time.clamp(first, last) - first) * last_column / (last - first)) as usize
+}
+
+/// The scroll position that puts `row` on screen, which is `scroll_offset` when it already is.
+fn reveal(scroll_offset: usize, content_height: usize, row: usize) -> usize {
+    // Author headers can take up every other screen line, so half a screen of rows always fits
+    let visible = (content_height / 2).max(1);
+    if row < scroll_offset || row >= scroll_offset + visible {
+        row.saturating_sub(visible / 2)
+    } else {
+        scroll_offset
+    }
+}
+
+/// Browse `history`, loading each version the first time it is shown.
+pub fn run_interactive_viewer(history: &LazyVersions, options: &ViewerOptions) -> Result<(), Box<dyn std::error::Error>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+
+    let mut viewer = Viewer::new(history, options);
+    loop {
+        viewer.request_current();
+        let ready = viewer.draw(&mut stdout)?;
+
+        // Wait for input, waking up to redraw once the version on screen has loaded
+        let generation = history.generation();
+        let event = loop {
+            if event::poll(LOAD_POLL_INTERVAL)? {
+                break Some(event::read()?);
+            }
+            if !ready && history.generation() != generation {
+                break None;
+            }
+        };
+
+        if let Some(event) = event {
+            if !viewer.handle_event(event) {
+                break;
+            }
+        }
+    }
+
+    // Cleanup
+    disable_raw_mode()?;
+    execute!(stdout, DisableMouseCapture, LeaveAlternateScreen)?;
+    Ok(())
+}